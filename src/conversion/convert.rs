@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::{
+    message_base::{Message, MessageBase, MessageBaseWriter},
+    util::echmoail::EchomailAddress,
+};
+
+#[derive(Error, Debug)]
+pub enum ConversionError {
+    #[error("Message number {0} occurs more than once, numbers can't be preserved")]
+    DuplicateMessageNumber(u32),
+}
+
+/// Options for `convert`
+#[derive(Debug, Default, Clone)]
+pub struct ConvertOptions {
+    skip_deleted: bool,
+    preserve_numbers: bool,
+    origin_address: Option<EchomailAddress>,
+    address_map: HashMap<EchomailAddress, EchomailAddress>,
+}
+
+impl ConvertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deleted messages aren't converted.
+    pub fn with_skip_deleted(mut self, skip_deleted: bool) -> Self {
+        self.skip_deleted = skip_deleted;
+        self
+    }
+
+    /// Converted messages keep their message number, unused numbers are skipped.
+    ///
+    /// # Remarks
+    /// Messages are converted in message number order instead of storage order.
+    pub fn with_preserve_numbers(mut self, preserve_numbers: bool) -> Self {
+        self.preserve_numbers = preserve_numbers;
+        self
+    }
+
+    /// Origin address for messages from formats without network addresses.
+    pub fn with_origin_address(mut self, address: EchomailAddress) -> Self {
+        self.origin_address = Some(address);
+        self
+    }
+
+    /// Replaces the origin address `from` with `to`.
+    pub fn with_address_mapping(mut self, from: EchomailAddress, to: EchomailAddress) -> Self {
+        self.address_map.insert(from, to);
+        self
+    }
+
+    fn map_origin(&self, origin: Option<EchomailAddress>) -> Option<EchomailAddress> {
+        origin
+            .map(|addr| self.address_map.get(&addr).copied().unwrap_or(addr))
+            .or(self.origin_address)
+    }
+}
+
+/// Outcome of `convert`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConvertReport {
+    pub converted_messages: u32,
+    pub skipped_messages: u32,
+    /// Maps source conference & message numbers to target message numbers
+    pub message_numbers: HashMap<(u16, u32), u32>,
+}
+
+/// Converts all messages of a message base into another message base.
+///
+/// # Remarks
+/// Reply links are mapped to the new message numbers, replies to messages
+/// that weren't converted (yet) lose their link.
+pub fn convert(
+    source: &dyn MessageBase,
+    target: &mut dyn MessageBaseWriter,
+    options: &ConvertOptions,
+) -> crate::Result<ConvertReport> {
+    convert_messages(source.messages(), target, options)
+}
+
+/// Like `convert` but takes the messages from an iterator.
+pub fn convert_messages(
+    messages: impl Iterator<Item = crate::Result<Message>>,
+    target: &mut dyn MessageBaseWriter,
+    options: &ConvertOptions,
+) -> crate::Result<ConvertReport> {
+    let mut report = ConvertReport::default();
+    let mut sorted = BTreeMap::new();
+    for msg in messages {
+        let msg = msg?;
+        if options.skip_deleted && msg.is_deleted() {
+            report.skipped_messages += 1;
+        } else if options.preserve_numbers {
+            let number = msg.number;
+            if sorted.insert(number, msg).is_some() {
+                return Err(ConversionError::DuplicateMessageNumber(number).into());
+            }
+        } else {
+            write_converted(msg, target, options, &mut report)?;
+        }
+    }
+
+    for msg in sorted.into_values() {
+        if target.next_message_number()? != msg.number {
+            target.set_next_message_number(msg.number)?;
+        }
+        write_converted(msg, target, options, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn write_converted(
+    mut msg: Message,
+    target: &mut dyn MessageBaseWriter,
+    options: &ConvertOptions,
+    report: &mut ConvertReport,
+) -> crate::Result<()> {
+    // QWK conferences reuse message numbers, so replies are mapped per conference
+    msg.reply_to = report
+        .message_numbers
+        .get(&(msg.conference, msg.reply_to))
+        .copied()
+        .unwrap_or_default();
+    msg.origin = options.map_origin(msg.origin);
+
+    let number = target.write_message(&msg)?;
+    report
+        .message_numbers
+        .insert((msg.conference, msg.number), number);
+    report.converted_messages += 1;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jam::{JamMessage, JamMessageBase},
+        qwk::QwkMessageBase,
+    };
+    use bstr::BString;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_convert_jam_to_jam() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let source = JamMessageBase::open("data/jam/general").unwrap();
+        let mut target = JamMessageBase::create(tmpdir.path().join("copy")).unwrap();
+        let report = convert(&source, &mut target, &ConvertOptions::new()).unwrap();
+        assert_eq!(report.converted_messages, 4);
+
+        let source: Vec<_> = source.messages().map(|m| m.unwrap()).collect();
+        let converted: Vec<_> = target.messages().map(|m| m.unwrap()).collect();
+        assert_eq!(source.len(), converted.len());
+        for (src, dst) in source.iter().zip(converted.iter()) {
+            assert_eq!(src.from, dst.from);
+            assert_eq!(src.to, dst.to);
+            assert_eq!(src.subject, dst.subject);
+            assert_eq!(src.date_written, dst.date_written);
+            assert_eq!(src.flags, dst.flags);
+            assert_eq!(src.reply_to, dst.reply_to);
+            assert_eq!(src.text, dst.text);
+            assert_eq!(src.kludges, dst.kludges);
+        }
+        assert_eq!(target.read_header(2).unwrap().reply1st, 3);
+    }
+
+    #[test]
+    fn test_convert_options() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let mut source = JamMessageBase::create(tmpdir.path().join("source")).unwrap();
+        let aka = EchomailAddress::new(1, 2, 3, 0);
+        for i in 0..4 {
+            let msg = JamMessage::new(&aka)
+                .with_subject(BString::from(format!("msg {}", i + 1)))
+                .with_reply_to(if i == 3 { 3 } else { 0 });
+            source.write_message(&msg).unwrap();
+        }
+        source.delete_message(2).unwrap();
+
+        let mut target = JamMessageBase::create(tmpdir.path().join("target")).unwrap();
+        let options = ConvertOptions::new()
+            .with_skip_deleted(true)
+            .with_preserve_numbers(true);
+        let report = convert(&source, &mut target, &options).unwrap();
+        assert_eq!(report.converted_messages, 3);
+        assert_eq!(report.skipped_messages, 1);
+        assert_eq!(target.active_messages(), 3);
+        assert!(target.read_header(2).is_err());
+        assert_eq!(target.read_header(4).unwrap().reply_to, 3);
+        assert!(target.check().unwrap().is_ok());
+
+        let mut target = JamMessageBase::create(tmpdir.path().join("renumbered")).unwrap();
+        let options = ConvertOptions::new().with_skip_deleted(true);
+        let report = convert(&source, &mut target, &options).unwrap();
+        assert_eq!(report.message_numbers[&(0, 4)], 3);
+        assert_eq!(target.read_header(3).unwrap().reply_to, 2);
+    }
+
+    #[test]
+    fn test_convert_origin_address() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let msg = |origin| Message {
+            origin,
+            text: BString::from("line 1\nline 2\n"),
+            ..Default::default()
+        };
+        let old = EchomailAddress::new(1, 2, 3, 0);
+        let new = EchomailAddress::new(2, 3, 4, 0);
+        let default = EchomailAddress::new(3, 4, 5, 6);
+        let options = ConvertOptions::new()
+            .with_address_mapping(old, new)
+            .with_origin_address(default);
+
+        let mut target = JamMessageBase::create(tmpdir.path().join("target")).unwrap();
+        let messages = vec![Ok(msg(Some(old))), Ok(msg(None))];
+        convert_messages(messages.into_iter(), &mut target, &options).unwrap();
+
+        let first = target.get_message(1).unwrap();
+        assert_eq!(first.origin, Some(new));
+        assert_eq!(first.text, "line 1\nline 2\n");
+        let header = target.read_header(1).unwrap();
+        assert_eq!(target.read_msg_text(&header).unwrap(), "line 1\rline 2\r");
+        assert_eq!(target.get_message(2).unwrap().origin, Some(default));
+    }
+
+    #[test]
+    fn test_convert_conference_replies() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let msg = |conference, number, reply_to| {
+            Ok(Message {
+                number,
+                conference,
+                reply_to,
+                ..Default::default()
+            })
+        };
+        // both conferences use the numbers 1 & 2
+        let messages = vec![msg(1, 1, 0), msg(2, 1, 0), msg(2, 2, 1), msg(1, 2, 1)];
+        let mut target = JamMessageBase::create(tmpdir.path().join("target")).unwrap();
+        let report =
+            convert_messages(messages.into_iter(), &mut target, &ConvertOptions::new()).unwrap();
+        assert_eq!(report.message_numbers[&(2, 2)], 3);
+        assert_eq!(target.read_header(3).unwrap().reply_to, 2);
+        assert_eq!(target.read_header(4).unwrap().reply_to, 1);
+    }
+
+    #[test]
+    fn test_convert_qwk_to_jam() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let source = QwkMessageBase::open("data/qwk", true).unwrap();
+        let mut target = JamMessageBase::create(tmpdir.path().join("target")).unwrap();
+        convert(&source, &mut target, &ConvertOptions::new()).unwrap();
+
+        let msg = target.get_message(1).unwrap();
+        assert_eq!(msg.subject, "test");
+        assert!(msg.text.starts_with(b"dwedfwefwe\n"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use crate::{
+    jam::JamMessageBase,
+    message_base::{Message, MessageBaseWriter},
+    pcboard::{PCBoardMessage, PCBoardMessageBase},
+};
+
+use super::{convert, ConvertOptions};
+
+/// Converts a JAM message base to a new PCBoard message base.
+///
+/// Message numbers are kept. Names & subjects longer than 25 chars are stored
+/// in extended headers, FTS kludges aren't converted.
+/// Texts that don't fit into the 255 blocks of a PCBoard message are cut off.
+pub fn convert_jam_to_pcboard(jam_path: &Path, pcboard_dest_path: &Path) -> crate::Result<()> {
+    let jam_base = JamMessageBase::open(jam_path)?;
+    let mut pcb_base = PCBoardMessageBase::create(pcboard_dest_path)?;
+    let options = ConvertOptions::new().with_preserve_numbers(true);
+    convert(&jam_base, &mut TruncatingWriter(&mut pcb_base), &options)?;
+    Ok(())
+}
+
+/// Writes messages to a PCBoard message base, cutting off texts that are too long.
+struct TruncatingWriter<'a>(&'a mut PCBoardMessageBase);
+
+impl MessageBaseWriter for TruncatingWriter<'_> {
+    fn write_message(&mut self, msg: &Message) -> crate::Result<u32> {
+        let mut pcb_msg = PCBoardMessage::from(msg);
+        if pcb_msg.truncate_text() {
+            log::warn!(
+                "message {} is too long for PCBoard, text truncated to {} bytes",
+                msg.number,
+                pcb_msg.text.len()
+            );
+        }
+        self.0.write_message(&pcb_msg)
+    }
+
+    fn next_message_number(&self) -> crate::Result<u32> {
+        Ok(self.0.next_message_number())
+    }
+
+    fn set_next_message_number(&mut self, number: u32) -> crate::Result<()> {
+        self.0.set_next_message_number(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jam::{attributes, JamMessage},
+        message_base::{flags, MessageBase},
+        pcboard::MessageStatus,
+        util::echmoail::EchomailAddress,
+    };
+    use bstr::{BString, ByteSlice};
+    use chrono::{NaiveDate, Timelike};
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_convert_jam_to_pcboard() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let converted = tmpdir.path().join("msgs");
+        convert_jam_to_pcboard(&PathBuf::from("data/jam/general"), &converted).unwrap();
+
+        let jam = JamMessageBase::open("data/jam/general").unwrap();
+        let pcb = PCBoardMessageBase::open(&converted).unwrap();
+        assert_eq!(jam.active_messages(), pcb.active_messages());
+
+        for jam_msg in MessageBase::messages(&jam) {
+            let jam_msg = jam_msg.unwrap();
+            let pcb_msg = pcb.get_message(jam_msg.number).unwrap();
+            assert_eq!(jam_msg.from, pcb_msg.from);
+            assert_eq!(jam_msg.to, pcb_msg.to);
+            assert_eq!(jam_msg.subject, pcb_msg.subject);
+            // PCBoard stores minutes only
+            assert_eq!(
+                jam_msg.date_written.with_second(0).unwrap(),
+                pcb_msg.date_written
+            );
+            assert_eq!(jam_msg.reply_to, pcb_msg.reply_to);
+            assert_eq!(jam_msg.is_private(), pcb_msg.is_private());
+            assert_eq!(jam_msg.text, pcb_msg.text.trim_end_with(|c| c == ' '));
+        }
+    }
+
+    #[test]
+    fn test_convert_fields() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let jam_path = tmpdir.path().join("jam");
+        let mut jam = JamMessageBase::create(&jam_path).unwrap();
+        let long_name = "A very long recipient name that needs the TO and TO2 extended headers";
+        let date_time = NaiveDate::from_ymd_opt(1995, 10, 17)
+            .unwrap()
+            .and_hms_opt(13, 37, 0)
+            .unwrap();
+        let msg = JamMessage::new(&EchomailAddress::default())
+            .with_from(BString::from("Sysop"))
+            .with_to(BString::from(long_name))
+            .with_subject(BString::from("A subject with more than 25 chars"))
+            .with_date_time(date_time)
+            .with_attributes(attributes::MSG_PRIVATE | attributes::MSG_READ)
+            .with_text(BString::from("line 1\rline 2\r"));
+        jam.write_message(&msg).unwrap();
+        jam.write_message(&msg.clone().with_reply_to(1)).unwrap();
+        jam.delete_message(1).unwrap();
+
+        let pcb_path = tmpdir.path().join("msgs");
+        convert_jam_to_pcboard(&jam_path, &pcb_path).unwrap();
+        let pcb = PCBoardMessageBase::open(&pcb_path).unwrap();
+        assert_eq!(pcb.active_messages(), 1);
+        assert_eq!(pcb.highest_message_number(), 2);
+
+        let deleted = pcb.read_message(1).unwrap();
+        assert!(deleted.is_deleted());
+
+        let pcb_msg = pcb.read_message(2).unwrap();
+        assert_eq!(pcb_msg.header.status, b'+');
+        assert_eq!(pcb_msg.get_status(), MessageStatus::Private);
+        assert_eq!(pcb_msg.header.to_field, &long_name[..25]);
+        assert_eq!(pcb_msg.header.date_time, "10-17-9513:37");
+        assert_eq!(pcb_msg.header.reply_to, 1);
+        assert_eq!(pcb_msg.extended_header.len(), 3);
+
+        let msg = pcb.get_message(2).unwrap();
+        assert_eq!(msg.to, long_name);
+        assert_eq!(msg.subject, "A subject with more than 25 chars");
+        assert!(msg.has_flag(flags::READ));
+        assert_eq!(msg.text.trim_end_with(|c| c == ' '), b"line 1\nline 2\n");
+    }
+
+    #[test]
+    fn test_convert_long_message() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let jam_path = tmpdir.path().join("jam");
+        let mut jam = JamMessageBase::create(&jam_path).unwrap();
+        let long_text = "a line of a very long message\r".repeat(2000);
+        let msg = JamMessage::new(&EchomailAddress::default())
+            .with_from(BString::from("Sysop"))
+            .with_to(BString::from("All"))
+            .with_subject(BString::from("Long"))
+            .with_text(BString::from(long_text.as_str()));
+        jam.write_message(&msg).unwrap();
+        jam.write_message(&msg.clone().with_text(BString::from("short\r")))
+            .unwrap();
+
+        let pcb_path = tmpdir.path().join("msgs");
+        convert_jam_to_pcboard(&jam_path, &pcb_path).unwrap();
+        let pcb = PCBoardMessageBase::open(&pcb_path).unwrap();
+        assert_eq!(pcb.active_messages(), 2);
+
+        let pcb_msg = pcb.read_message(1).unwrap();
+        assert_eq!(pcb_msg.header.num_blocks, u8::MAX);
+        assert!(long_text
+            .replace('\r', "\n")
+            .as_bytes()
+            .starts_with(&pcb_msg.text));
+        assert_eq!(
+            pcb.get_message(2).unwrap().text.trim_end_with(|c| c == ' '),
+            b"short\n"
+        );
+    }
+}
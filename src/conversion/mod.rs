@@ -1,5 +1,14 @@
+pub mod convert;
+pub use convert::*;
+
+pub mod jam_to_pcboard;
+pub use jam_to_pcboard::*;
+
 pub mod pcboard_to_jam;
 pub use pcboard_to_jam::*;
 
 pub mod qwk_to_jam;
 pub use qwk_to_jam::*;
+
+pub mod qwk_import;
+pub use qwk_import::*;
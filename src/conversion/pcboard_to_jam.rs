@@ -1,150 +1,54 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    path::Path,
-};
+use std::path::Path;
 
 use crate::{
-    jam::{
-        self,
-        msg_header::{MessageSubfield, SubfieldType},
-        JamMessage, JamMessageBase,
-    },
-    pcboard::{message_header::ExtendedHeaderInformation, PCBoardMessage, PCBoardMessageBase},
+    jam::{JamMessage, JamMessageBase},
+    message_base::{Message, MessageBaseWriter},
+    pcboard::PCBoardMessageBase,
     util::echmoail::EchomailAddress,
 };
 
-fn get_jam_attributes(msg: &PCBoardMessage) -> u32 {
-    let mut result = 0;
-    match msg.get_status() {
-        crate::pcboard::MessageStatus::Private | crate::pcboard::MessageStatus::CommentToSysop => {
-            result |= jam::attributes::MSG_PRIVATE
-        }
-        _ => {}
-    }
-
-    if msg.is_read() {
-        result |= jam::attributes::MSG_READ;
-    }
-
-    if msg.is_deleted() {
-        result |= jam::attributes::MSG_DELETED;
-    }
-
-    if msg.header.has_attach() {
-        result |= jam::attributes::MSG_FILEATTACH;
-    }
-
-    if msg.header.has_reqrr() {
-        result |= jam::attributes::MSG_RECEIPTREQ;
-    }
-
-    result
-}
+use super::{convert, ConvertOptions};
 
+/// Converts a PCBoard message base to a new JAM message base.
+///
+/// Message numbers are kept and the MSGIDs are generated using `aka`.
+/// The message text is copied as is, `convert` writes JAM (CR) line endings instead.
 pub fn convert_pcboard_to_jam(
     pcboard_path: &Path,
     jam_dest_path: &Path,
     aka: &EchomailAddress,
 ) -> crate::Result<()> {
     let pcb_base = PCBoardMessageBase::open(pcboard_path)?;
+    let mut jam_base = JamMessageBase::create(jam_dest_path)?;
+    let options = ConvertOptions::new()
+        .with_preserve_numbers(true)
+        .with_origin_address(*aka);
+    convert(&pcb_base, &mut RawTextWriter(&mut jam_base), &options)?;
+    Ok(())
+}
 
-    let mut jam_messages = BTreeMap::new();
-    let mut message_ids = HashMap::new();
-
-    for msg_result in pcb_base.iter() {
-        let msg = msg_result?;
-        let attribute = get_jam_attributes(&msg);
-        let time = msg.header.date_time();
-
-        let mut to = msg.header.to_field;
-        let mut from = msg.header.from_field;
-        let mut subj = msg.header.subj_field;
-        let mut sub_fields = Vec::new();
-        for header in msg.extended_header {
-            match header.info {
-                ExtendedHeaderInformation::To => {
-                    to.clone_from(&header.content);
-                }
-                ExtendedHeaderInformation::From => {
-                    from.clone_from(&header.content);
-                }
-                ExtendedHeaderInformation::Subject => {
-                    subj.clone_from(&header.content);
-                }
-                ExtendedHeaderInformation::To2 => {
-                    to.append(&mut header.content.clone());
-                }
-                ExtendedHeaderInformation::From2 => {
-                    from.append(&mut header.content.clone());
-                }
-                ExtendedHeaderInformation::Attach => {
-                    sub_fields.push(MessageSubfield::new(SubfieldType::EnclFile, header.content));
-                }
-
-                ExtendedHeaderInformation::List
-                | ExtendedHeaderInformation::Route
-                | ExtendedHeaderInformation::Origin
-                | ExtendedHeaderInformation::Reqrr
-                | ExtendedHeaderInformation::Ackrr
-                | ExtendedHeaderInformation::Ackname
-                | ExtendedHeaderInformation::Packout
-                | ExtendedHeaderInformation::Forward
-                | ExtendedHeaderInformation::Ufollow
-                | ExtendedHeaderInformation::Unewsgr => {
-                    // ignore
-                    continue;
-                }
-            }
-        }
-
-        let new_msg = JamMessage::new(msg.header.msg_number, aka)
-            .with_reply_to(msg.header.reply_to)
-            .with_date_time(time)
-            .with_text(msg.text)
-            .with_attributes(attribute)
-            .with_password(&msg.header.password)
-            .with_from(from)
-            .with_to(to.clone())
-            .with_subject(subj.clone());
-        message_ids.insert(msg.header.msg_number, new_msg.get_msgid_crc());
-        jam_messages.insert(msg.header.msg_number, new_msg);
-    }
-
-    // Setting reply information for each message.
-    // TODO: reply_next
-    // Does it even make any sense at all?
+/// Writes messages to a JAM message base without converting the line endings.
+struct RawTextWriter<'a>(&'a mut JamMessageBase);
 
-    let mut reply_next = Vec::new();
-    for (i, msg) in &mut jam_messages {
-        let reply_to = msg.get_reply_to();
-        if reply_to != 0 && reply_to != *i {
-            if let Some(crc) = message_ids.get_mut(&reply_to) {
-                msg.set_reply_crc(*crc);
-                reply_next.push((reply_to, *i));
-            }
-        }
+impl MessageBaseWriter for RawTextWriter<'_> {
+    fn write_message(&mut self, msg: &Message) -> crate::Result<u32> {
+        self.0
+            .write_message(&JamMessage::from(msg).with_text(msg.text.clone()))
     }
 
-    for (reply_to, rep1st) in reply_next {
-        if let Some(msg) = jam_messages.get_mut(&reply_to) {
-            if msg.get_reply1st() == 0 {
-                msg.set_reply1st(rep1st);
-            }
-        }
+    fn next_message_number(&self) -> crate::Result<u32> {
+        self.0.next_message_number()
     }
 
-    // write jam message base
-    let mut jam_base = JamMessageBase::create(jam_dest_path)?;
-    for msg in jam_messages.values() {
-        jam_base.write_message(msg)?;
+    fn set_next_message_number(&mut self, number: u32) -> crate::Result<()> {
+        self.0.set_next_message_number(number)
     }
-    jam_base.write_jhr_header()?;
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bstr::ByteSlice;
     use pretty_assertions::assert_eq;
     use std::path::PathBuf;
     use tempfile::TempDir;
@@ -177,4 +81,22 @@ mod tests {
             assert!(pcb_msg.text == jam_txt);
         }
     }
+
+    #[test]
+    fn test_convert_line_endings() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let pcb = PCBoardMessageBase::open("data/pcboard/test").unwrap();
+        let mut jam = JamMessageBase::create(tmpdir.path().join("jambase")).unwrap();
+        convert(&pcb, &mut jam, &ConvertOptions::new()).unwrap();
+
+        for i in 1..=pcb.active_messages() {
+            let pcb_msg = pcb.read_message(i).unwrap();
+            let jam_msg = jam.read_header(i).unwrap();
+            // JAM uses CR as line separator
+            assert_eq!(
+                pcb_msg.text.replace("\n", "\r"),
+                jam.read_msg_text(&jam_msg).unwrap()
+            );
+        }
+    }
 }
@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bstr::BString;
+
+use crate::{
+    jam::JamMessageBase,
+    qwk::{control::Conference, QwkMessageBase},
+    util::echmoail::EchomailAddress,
+};
+
+use super::{convert_qwk_conferences_to_jam, QwkConversionReport};
+
+/// Maps QWK conferences to JAM message bases.
+///
+/// Conferences can be mapped by number or by name (case insensitive),
+/// a mapped number takes precedence over a mapped name.
+#[derive(Debug, Default, Clone)]
+pub struct QwkAreaMapping {
+    by_number: HashMap<u16, PathBuf>,
+    by_name: HashMap<BString, PathBuf>,
+}
+
+impl QwkAreaMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps a conference number to a JAM base path (without extension)
+    pub fn with_conference<P: AsRef<Path>>(mut self, number: u16, jam_path: P) -> Self {
+        self.by_number.insert(number, jam_path.as_ref().into());
+        self
+    }
+
+    /// Maps a conference name to a JAM base path (without extension)
+    pub fn with_conference_name<P: AsRef<Path>>(mut self, name: &str, jam_path: P) -> Self {
+        self.by_name
+            .insert(name.to_ascii_lowercase().into(), jam_path.as_ref().into());
+        self
+    }
+
+    pub fn get_path(&self, conference: &Conference) -> Option<&Path> {
+        self.by_number
+            .get(&conference.number)
+            .or_else(|| {
+                let mut name = conference.name.clone();
+                name.make_ascii_lowercase();
+                self.by_name.get(&name)
+            })
+            .map(PathBuf::as_path)
+    }
+}
+
+/// Outcome of `import_qwk_packet`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QwkImportReport {
+    /// Converted messages and messages of unmapped conferences
+    pub conversion: QwkConversionReport,
+    /// JAM message bases that didn't exist before the import
+    pub created_bases: Vec<PathBuf>,
+    /// Conferences with NDX files that needed the block offset fixup
+    pub index_fixups: Vec<u16>,
+}
+
+/// Imports the mail of all conferences of a QWK packet into the mapped JAM message bases.
+///
+/// # Remarks
+/// The mail is read through the NNN.NDX files of the conferences and routed with
+/// `convert_qwk_conferences_to_jam`. Missing JAM message bases are created,
+/// several conferences may be mapped to the same message base.
+pub fn import_qwk_packet(
+    qwk: &QwkMessageBase,
+    mapping: &QwkAreaMapping,
+    aka: &EchomailAddress,
+) -> crate::Result<QwkImportReport> {
+    let mut report = QwkImportReport::default();
+    // conferences mapped to the same path share one open message base
+    let mut jam_bases: HashMap<&Path, JamMessageBase> = HashMap::new();
+
+    for conference in qwk.get_conferences() {
+        let mail = qwk.read_conference(conference.number)?;
+        if mail.index_fixup {
+            report.index_fixups.push(conference.number);
+        }
+        if mail.messages.is_empty() {
+            continue;
+        }
+        let jam_path = mapping.get_path(conference);
+        let mut targets = HashMap::new();
+        if let Some(jam_path) = jam_path {
+            let jam_base = match jam_bases.remove(jam_path) {
+                Some(jam_base) => jam_base,
+                None if jam_path.with_extension("jhr").exists() => JamMessageBase::open(jam_path)?,
+                None => {
+                    report.created_bases.push(jam_path.to_path_buf());
+                    JamMessageBase::create(jam_path)?
+                }
+            };
+            targets.insert(conference.number, jam_base);
+        }
+
+        let conversion = convert_qwk_conferences_to_jam(&mail.messages, &mut targets, aka)?;
+        for (number, count) in conversion.converted_messages {
+            *report
+                .conversion
+                .converted_messages
+                .entry(number)
+                .or_default() += count;
+        }
+        for (number, count) in conversion.skipped_messages {
+            *report
+                .conversion
+                .skipped_messages
+                .entry(number)
+                .or_default() += count;
+        }
+        if let (Some(jam_path), Some(jam_base)) = (jam_path, targets.remove(&conference.number)) {
+            jam_bases.insert(jam_path, jam_base);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        message_base::Message,
+        qwk::{control::ControlDat, packet_builder::QwkPacketBuilder, qwk_message::QWKMessage},
+    };
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    /// Writes a packet with the conferences "Main Board" (0) and "Second Board" (1).
+    fn write_test_packet(path: &Path, messages: &[(u16, &str)]) -> QwkMessageBase {
+        let control_dat = ControlDat {
+            conferences: vec![
+                Conference {
+                    number: 0,
+                    name: BString::from("Main Board"),
+                },
+                Conference {
+                    number: 1,
+                    name: BString::from("Second Board"),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut builder = QwkPacketBuilder::new(control_dat);
+        for (conference, subject) in messages {
+            let msg = Message {
+                from: BString::from("SYSOP"),
+                to: BString::from("ALL"),
+                subject: BString::from(*subject),
+                text: BString::from("text\n"),
+                ..Default::default()
+            };
+            builder.add_message(QWKMessage::from_message(&msg, *conference));
+        }
+        builder.write(path).unwrap();
+        QwkMessageBase::open(path, true).unwrap()
+    }
+
+    #[test]
+    fn test_import_by_name() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let jam_path = tmpdir.path().join("main");
+        let qwk = write_test_packet(&tmpdir.path().join("packet"), &[(0, "test")]);
+        let mapping = QwkAreaMapping::new().with_conference_name("MAIN BOARD", &jam_path);
+
+        let report = import_qwk_packet(&qwk, &mapping, &EchomailAddress::default()).unwrap();
+        assert_eq!(
+            report.conversion.converted_messages,
+            BTreeMap::from([(0, 1)])
+        );
+        assert!(report.conversion.skipped_messages.is_empty());
+        assert_eq!(report.created_bases, vec![jam_path.clone()]);
+        assert!(report.index_fixups.is_empty());
+
+        // importing again appends to the existing base
+        let report = import_qwk_packet(&qwk, &mapping, &EchomailAddress::default()).unwrap();
+        assert!(report.created_bases.is_empty());
+        let jam_base = JamMessageBase::open(&jam_path).unwrap();
+        assert_eq!(jam_base.active_messages(), 2);
+        assert_eq!(
+            jam_base.read_header(2).unwrap().get_subject().unwrap(),
+            "test"
+        );
+    }
+
+    #[test]
+    fn test_import_unmapped() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let qwk = write_test_packet(&tmpdir.path().join("packet"), &[(0, "test")]);
+        let mapping = QwkAreaMapping::new()
+            .with_conference(1, tmpdir.path().join("other"))
+            .with_conference_name("Other", tmpdir.path().join("other"));
+
+        let report = import_qwk_packet(&qwk, &mapping, &EchomailAddress::default()).unwrap();
+        assert!(report.conversion.converted_messages.is_empty());
+        assert_eq!(report.conversion.skipped_messages, BTreeMap::from([(0, 1)]));
+        assert!(report.created_bases.is_empty());
+    }
+
+    #[test]
+    fn test_import_shared_base() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let jam_path = tmpdir.path().join("shared");
+        let qwk = write_test_packet(
+            &tmpdir.path().join("packet"),
+            &[(0, "first"), (1, "second"), (0, "third")],
+        );
+        let mapping = QwkAreaMapping::new()
+            .with_conference(0, &jam_path)
+            .with_conference(1, &jam_path);
+        let report = import_qwk_packet(&qwk, &mapping, &EchomailAddress::default()).unwrap();
+        assert_eq!(
+            report.conversion.converted_messages,
+            BTreeMap::from([(0, 2), (1, 1)])
+        );
+        assert_eq!(report.created_bases, vec![jam_path.clone()]);
+
+        let jam_base = JamMessageBase::open(&jam_path).unwrap();
+        assert_eq!(jam_base.active_messages(), 3);
+        let subjects: Vec<_> = (1..=3)
+            .map(|number| {
+                jam_base
+                    .read_header(number)
+                    .unwrap()
+                    .get_subject()
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+        assert_eq!(subjects, vec!["first", "third", "second"]);
+    }
+}
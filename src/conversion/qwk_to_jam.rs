@@ -1,49 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+
 use crate::{
-    jam::{JamMessage, JamMessageBase},
-    qwk::qwk_message::QWKMessage,
+    jam::JamMessageBase, message_base::Message, qwk::qwk_message::QWKMessage,
     util::echmoail::EchomailAddress,
 };
 
+use super::{convert_messages, ConvertOptions};
+
+/// Outcome of `convert_qwk_conferences_to_jam`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QwkConversionReport {
+    /// Converted messages per conference number
+    pub converted_messages: BTreeMap<u16, u32>,
+    /// Messages per conference number that had no target message base
+    pub skipped_messages: BTreeMap<u16, u32>,
+}
+
+/// Converts QWK mail into a single JAM message base.
+///
+/// # Remarks
+/// QWK reference numbers are only unique within a conference,
+/// so reply links are only mapped between messages of the same conference.
 pub fn convert_qwk_to_jam(
     qwk_mail: &[QWKMessage],
     jam_base: &mut JamMessageBase,
+    aka: &EchomailAddress,
 ) -> crate::Result<()> {
-    for mail in qwk_mail {
-        let mut jam_msg = JamMessage::new(mail.msg_number, &EchomailAddress::default());
+    let options = ConvertOptions::new().with_origin_address(*aka);
+    for mail in group_by_conference(qwk_mail).values() {
+        convert_messages(to_messages(mail), jam_base, &options)?;
+    }
+    Ok(())
+}
 
-        jam_msg = jam_msg
-            .with_from(mail.from.clone())
-            .with_to(mail.to.clone())
-            .with_subject(mail.subj.clone())
-            .with_date_time(mail.date_time())
-            .with_is_deleted(mail.is_deleted());
+/// Converts QWK mail into one JAM message base per conference.
+///
+/// Mail of conferences without an entry in `jam_bases` is skipped.
+pub fn convert_qwk_conferences_to_jam(
+    qwk_mail: &[QWKMessage],
+    jam_bases: &mut HashMap<u16, JamMessageBase>,
+    aka: &EchomailAddress,
+) -> crate::Result<QwkConversionReport> {
+    let options = ConvertOptions::new().with_origin_address(*aka);
+    let mut report = QwkConversionReport::default();
+    for (conference, mail) in group_by_conference(qwk_mail) {
+        match jam_bases.get_mut(&conference) {
+            Some(jam_base) => {
+                let res = convert_messages(to_messages(&mail), jam_base, &options)?;
+                report
+                    .converted_messages
+                    .insert(conference, res.converted_messages);
+            }
+            None => {
+                report
+                    .skipped_messages
+                    .insert(conference, mail.len() as u32);
+            }
+        }
+    }
+    Ok(report)
+}
 
-        jam_base.write_message(&jam_msg)?;
+fn group_by_conference(qwk_mail: &[QWKMessage]) -> BTreeMap<u16, Vec<&QWKMessage>> {
+    let mut res: BTreeMap<u16, Vec<&QWKMessage>> = BTreeMap::new();
+    for mail in qwk_mail {
+        res.entry(mail.conference_number).or_default().push(mail);
     }
-    jam_base.write_jhr_header()?;
+    res
+}
 
-    Ok(())
+fn to_messages<'a>(mail: &'a [&QWKMessage]) -> impl Iterator<Item = crate::Result<Message>> + 'a {
+    mail.iter().map(|mail| Ok(Message::from((*mail).clone())))
 }
-/*
 
 #[cfg(test)]
 mod tests {
-    use crate::qwk::QwkMessageBase;
-
     use super::*;
+    use crate::{
+        jam::attributes,
+        qwk::{qwk_message::MSG_ACTIVE, QwkMessageBase},
+    };
+    use bstr::BString;
     use pretty_assertions::assert_eq;
-    use std::{path::PathBuf, time::SystemTime};
     use tempfile::TempDir;
 
+    fn mail(conference: u16, number: u32, reply: u32, status: u8) -> QWKMessage {
+        QWKMessage {
+            status,
+            msg_number: number,
+            date_time: BString::from("04-07-2410:59"),
+            to: BString::from("ALL"),
+            from: BString::from("SYSOP"),
+            subj: BString::from(format!("Message {}", number)),
+            password: BString::default(),
+            ref_msg_number: reply,
+            active_flag: MSG_ACTIVE,
+            conference_number: conference,
+            logical_message_number: 0,
+            net_tag: b' ',
+            text: BString::from(format!("Text {}\n   ", number)),
+        }
+    }
+
     #[test]
-    fn test_read_message_reader() {
-        let mut msg_base = QwkMessageBase::open("/home/mkrueger/Downloads/large", true).unwrap();
-        msg_base.index_offset_bug = true;
+    fn test_convert_qwk_to_jam() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let mut jam_base = JamMessageBase::create(tmpdir.path().join("jambase")).unwrap();
+        let mut long = mail(1, 10, 0, b'+');
+        long.subj =
+            BString::from("A subject that doesn't fit into the 25 characters of a QWK header");
+        long.password = BString::from("SECRET");
+        let qwk_mail = vec![long, mail(2, 10, 0, b' '), mail(1, 11, 10, b'-')];
+        let aka = EchomailAddress::new(1, 2, 3, 0);
+        convert_qwk_to_jam(&qwk_mail, &mut jam_base, &aka).unwrap();
+
+        assert_eq!(jam_base.active_messages(), 3);
+        let header = jam_base.read_header(1).unwrap();
+        assert_eq!(header.get_subject().unwrap(), &qwk_mail[0].subj);
+        assert_eq!(
+            header.attributes,
+            attributes::MSG_PRIVATE | attributes::MSG_READ
+        );
+        assert!(header.is_password_valid("SECRET"));
+        assert!(header.get_msgid().unwrap().starts_with(b"1:2/3 "));
+        assert_eq!(header.reply1st, 2);
+        assert_eq!(jam_base.read_msg_text(&header).unwrap(), "Text 10\r");
 
-        let mail: Vec<QWKMessage> = msg_base.iter().flatten().collect();
-        println!("{:?}", mail.len());
-        convert_qwk_to_jam(&mail, &mut JamMessageBase::create(PathBuf::from("/home/mkrueger/Downloads/jam_base")).unwrap()).unwrap();
+        // messages are grouped by conference, replies are only linked inside a conference
+        let reply = jam_base.read_header(2).unwrap();
+        assert_eq!(reply.reply_to, 1);
+        assert_eq!(reply.attributes, attributes::MSG_READ);
+        let other_conference = jam_base.read_header(3).unwrap();
+        assert_eq!(other_conference.reply_to, 0);
+        assert_eq!(other_conference.attributes, 0);
     }
-}
 
-*/
+    #[test]
+    fn test_convert_qwk_conferences() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let qwk_mail = vec![
+            mail(1, 10, 0, b' '),
+            mail(2, 10, 0, b' '),
+            mail(1, 11, 10, b' '),
+            mail(3, 1, 0, b' '),
+        ];
+        let mut jam_bases = HashMap::new();
+        for conference in [1, 2] {
+            let path = tmpdir.path().join(format!("conf{}", conference));
+            jam_bases.insert(conference, JamMessageBase::create(path).unwrap());
+        }
+        let report =
+            convert_qwk_conferences_to_jam(&qwk_mail, &mut jam_bases, &EchomailAddress::default())
+                .unwrap();
+        assert_eq!(report.converted_messages, BTreeMap::from([(1, 2), (2, 1)]));
+        assert_eq!(report.skipped_messages, BTreeMap::from([(3, 1)]));
+
+        let conf1 = &jam_bases[&1];
+        assert_eq!(conf1.active_messages(), 2);
+        assert_eq!(conf1.read_header(2).unwrap().reply_to, 1);
+        assert_eq!(conf1.read_header(1).unwrap().reply1st, 2);
+        assert_eq!(jam_bases[&2].active_messages(), 1);
+    }
+
+    #[test]
+    fn test_convert_qwk_packet() {
+        let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+        let qwk = QwkMessageBase::open("data/qwk", true).unwrap();
+        let mail: Vec<QWKMessage> = qwk.iter().flatten().collect();
+        let mut jam_base = JamMessageBase::create(tmpdir.path().join("jambase")).unwrap();
+        convert_qwk_to_jam(&mail, &mut jam_base, &EchomailAddress::default()).unwrap();
+
+        let header = jam_base.read_header(1).unwrap();
+        assert_eq!(header.get_from().unwrap(), "SYSOP");
+        let text = jam_base.read_msg_text(&header).unwrap();
+        assert!(text.starts_with(b"dwedfwefwe\r"));
+        assert!(!text.ends_with(b" "));
+    }
+}
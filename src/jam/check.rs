@@ -0,0 +1,232 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use super::{
+    extensions, jdx_record::JamIndexRecord, jhr_header::JHRHeaderInfo,
+    msg_header::JamMessageHeader, JamMessageBase, JAM_SIGNATURE,
+};
+
+/// A problem found by `JamMessageBase::check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JamIssue {
+    /// The .JDX file size is not a multiple of the record size
+    IndexFileTruncated(u64),
+
+    /// The index record doesn't point to a valid "JAM\0" header
+    InvalidHeader {
+        message_number: u32,
+        header_offset: u32,
+    },
+
+    /// The header at the index position has a different message number
+    MessageNumberMismatch {
+        message_number: u32,
+        header_number: u32,
+    },
+
+    /// The message text is not inside the .JDT file
+    TextOutOfRange {
+        message_number: u32,
+        offset: u32,
+        txt_len: u32,
+    },
+
+    /// The recipient crc of the index record doesn't match the header recipient
+    RecipientCrcMismatch {
+        message_number: u32,
+        index_crc: u32,
+        header_crc: u32,
+    },
+
+    /// ActiveMsgs in the .JHR header info doesn't match the active messages
+    ActiveMessagesMismatch { header: u32, actual: u32 },
+
+    /// The .JDX file has more records than message numbers are left above BaseMsgNum
+    MessageNumberOverflow { base_msg_num: u32 },
+}
+
+/// Outcome of `JamMessageBase::check`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamCheckReport {
+    /// Number of index records that point to a message header
+    pub checked_messages: u32,
+    pub issues: Vec<JamIssue>,
+}
+
+impl JamCheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Outcome of `JamMessageBase::repair`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamRepairReport {
+    /// Messages found in the .JHR file
+    pub messages: u32,
+    /// Active (not deleted) messages found in the .JHR file
+    pub active_messages: u32,
+    /// Bytes of the .JHR file that didn't contain a readable header
+    pub unreadable_bytes: u64,
+    /// The new lowest message number
+    pub base_msg_num: u32,
+}
+
+impl JamMessageBase {
+    /// Validates the message base.
+    ///
+    /// Every .JDX record needs to point to a valid header with the right message number
+    /// and recipient crc, the message text needs to be inside the .JDT file and
+    /// ActiveMsgs needs to match the number of active messages.
+    pub fn check(&self) -> crate::Result<JamCheckReport> {
+        let mut report = JamCheckReport::default();
+
+        let header_data = fs::read(self.file_name.with_extension(extensions::HEADER_DATA))?;
+        let text_len = fs::metadata(self.file_name.with_extension(extensions::TEXT_DATA))?.len();
+        let index_data = fs::read(self.file_name.with_extension(extensions::MESSAGE_INDEX))?;
+        if index_data.len() as u64 % JamIndexRecord::RECORD_SIZE != 0 {
+            report
+                .issues
+                .push(JamIssue::IndexFileTruncated(index_data.len() as u64));
+        }
+
+        let mut active = 0;
+        let mut data = &index_data[..];
+        let mut message_number = Some(self.header_info.base_msg_num);
+        while data.len() as u64 >= JamIndexRecord::RECORD_SIZE {
+            let Some(cur_number) = message_number else {
+                report.issues.push(JamIssue::MessageNumberOverflow {
+                    base_msg_num: self.header_info.base_msg_num,
+                });
+                break;
+            };
+            message_number = cur_number.checked_add(1);
+            let record = JamIndexRecord::load(&mut data)?;
+            if record.is_unused() {
+                continue;
+            }
+            report.checked_messages += 1;
+
+            let header = header_data
+                .get(record.header_offset as usize..)
+                .filter(|_| record.header_offset as u64 >= JHRHeaderInfo::JHR_HEADER_SIZE)
+                .and_then(|mut data| JamMessageHeader::read(&mut data).ok());
+            let Some(header) = header else {
+                report.issues.push(JamIssue::InvalidHeader {
+                    message_number: cur_number,
+                    header_offset: record.header_offset,
+                });
+                continue;
+            };
+
+            if !header.is_deleted() {
+                active += 1;
+            }
+            if header.message_number != cur_number {
+                report.issues.push(JamIssue::MessageNumberMismatch {
+                    message_number: cur_number,
+                    header_number: header.message_number,
+                });
+            }
+            if header.offset as u64 + header.txt_len as u64 > text_len {
+                report.issues.push(JamIssue::TextOutOfRange {
+                    message_number: cur_number,
+                    offset: header.offset,
+                    txt_len: header.txt_len,
+                });
+            }
+            let header_crc = header.get_to_crc();
+            if record.to_crc != header_crc {
+                report.issues.push(JamIssue::RecipientCrcMismatch {
+                    message_number: cur_number,
+                    index_crc: record.to_crc,
+                    header_crc,
+                });
+            }
+        }
+
+        if active != self.header_info.active_msgs {
+            report.issues.push(JamIssue::ActiveMessagesMismatch {
+                header: self.header_info.active_msgs,
+                actual: active,
+            });
+        }
+        Ok(report)
+    }
+
+    /// Rebuilds the .JDX file from a scan of the .JHR file and recomputes the header counters.
+    ///
+    /// # Remarks
+    /// If a message number occurs more than once (updated headers get appended to the .JHR file)
+    /// the last active header wins.
+    pub fn repair(&mut self) -> crate::Result<JamRepairReport> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let header_data = self.with_header_file(|file| {
+            let mut data = Vec::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        })?;
+
+        let mut report = JamRepairReport::default();
+        let mut headers: BTreeMap<u32, (u32, JamMessageHeader)> = BTreeMap::new();
+        let mut pos = JHRHeaderInfo::JHR_HEADER_SIZE as usize;
+        while pos < header_data.len() {
+            let mut data = &header_data[pos..];
+            let header = match JamMessageHeader::read(&mut data) {
+                Ok(header) if header.message_number != 0 => header,
+                _ => {
+                    // resync at the next header signature
+                    let next = header_data[pos + 1..]
+                        .windows(JAM_SIGNATURE.len())
+                        .position(|w| w == JAM_SIGNATURE)
+                        .map_or(header_data.len(), |p| pos + 1 + p);
+                    report.unreadable_bytes += (next - pos) as u64;
+                    pos = next;
+                    continue;
+                }
+            };
+            let offset = pos as u32;
+            pos = header_data.len() - data.len();
+
+            match headers.get(&header.message_number) {
+                Some((_, old)) if !old.is_deleted() && header.is_deleted() => {}
+                _ => {
+                    headers.insert(header.message_number, (offset, header));
+                }
+            }
+        }
+
+        if let Some(first) = headers.keys().next() {
+            self.header_info.base_msg_num = *first;
+        }
+        let mut writer = BufWriter::new(File::create(
+            self.file_name.with_extension(extensions::MESSAGE_INDEX),
+        )?);
+        if let Some(last) = headers.keys().next_back() {
+            for number in self.header_info.base_msg_num..=*last {
+                let record = match headers.get(&number) {
+                    Some((offset, header)) => {
+                        report.messages += 1;
+                        if !header.is_deleted() {
+                            report.active_messages += 1;
+                        }
+                        JamIndexRecord::new(header.get_to_crc(), *offset)
+                    }
+                    None => JamIndexRecord::UNUSED,
+                };
+                record.write(&mut writer)?;
+            }
+        }
+        writer.flush()?;
+
+        self.header_info.active_msgs = report.active_messages;
+        self.write_jhr_header()?;
+        report.base_msg_num = self.header_info.base_msg_num;
+        Ok(report)
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{Read, Write};
+
+/// One record of the .JDX file.
+///
+/// The record number (+BaseMsgNum) within the .JDX file determines a
+/// message's number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JamIndexRecord {
+    /// CRC-32 of the recipient's name (lowercase)
+    pub to_crc: u32,
+    /// Physical offset of the message header in the .JHR file
+    pub header_offset: u32,
+}
+
+impl JamIndexRecord {
+    pub const RECORD_SIZE: u64 = 8;
+
+    /// If both ulongs are -1 (ffffffffH), there is no corresponding message
+    /// header.
+    pub const UNUSED: JamIndexRecord = JamIndexRecord {
+        to_crc: u32::MAX,
+        header_offset: u32::MAX,
+    };
+
+    pub fn new(to_crc: u32, header_offset: u32) -> Self {
+        Self {
+            to_crc,
+            header_offset,
+        }
+    }
+
+    /// True, if there is no message header for this record (message got packed away)
+    pub fn is_unused(&self) -> bool {
+        self.header_offset == u32::MAX
+    }
+
+    pub fn load<R: Read>(file: &mut R) -> crate::Result<Self> {
+        let data = &mut [0; Self::RECORD_SIZE as usize];
+        file.read_exact(data)?;
+        let mut data = &data[..];
+        convert_u32!(to_crc, data);
+        convert_u32!(header_offset, data);
+        Ok(Self {
+            to_crc,
+            header_offset,
+        })
+    }
+
+    pub fn write<W: Write>(&self, file: &mut W) -> crate::Result<()> {
+        file.write_all(&self.to_crc.to_le_bytes())?;
+        file.write_all(&self.header_offset.to_le_bytes())?;
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     fs::{self, File},
-    io::{BufWriter, Read, Seek, Write},
+    io::{Read, Seek, Write},
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -11,7 +11,7 @@ use crate::jam::{JamError, JAM_SIGNATURE};
 /// .JHR files.
 ///
 /// The first actual message header starts at offset 1024 in the .JHR file.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct JHRHeaderInfo {
     /// <J><A><M> followed by <NUL>
     //pub signature: u32,
@@ -86,7 +86,7 @@ impl JHRHeaderInfo {
         Ok(())
     }
 
-    pub(crate) fn update(&mut self, file: &mut BufWriter<File>) -> crate::Result<()> {
+    pub(crate) fn update<W: Write + Seek>(&mut self, file: &mut W) -> crate::Result<()> {
         file.seek(std::io::SeekFrom::Start(8))?;
         self.mod_counter = self.mod_counter.wrapping_add(1);
         file.write_all(&self.mod_counter.to_le_bytes())?;
@@ -1,9 +1,6 @@
-use std::{
-    fs::File,
-    io::{BufReader, Read, Write},
-};
+use std::io::{Read, Write};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct JamLastReadStorage {
     pub user_crc: u32,      // CRC-32 of user name (lowercase)   (1)
     pub user_id: u32,       // Unique UserID
@@ -12,9 +9,14 @@ pub struct JamLastReadStorage {
 }
 
 impl JamLastReadStorage {
-    const LAST_READ_SIZE: usize = 16;
+    pub const LAST_READ_SIZE: usize = 16;
 
-    pub fn load(file: &mut BufReader<File>) -> crate::Result<Self> {
+    /// If the "lastread" record is deleted, UserCRC and UserID are both set to -1
+    pub fn is_deleted(&self) -> bool {
+        self.user_crc == u32::MAX && self.user_id == u32::MAX
+    }
+
+    pub fn load<R: Read>(file: &mut R) -> crate::Result<Self> {
         let data = &mut [0; Self::LAST_READ_SIZE];
         file.read_exact(data)?;
         let mut data = &data[..];
@@ -30,7 +32,7 @@ impl JamLastReadStorage {
         })
     }
 
-    pub fn write(&self, file: &mut File) -> crate::Result<()> {
+    pub fn write<W: Write>(&self, file: &mut W) -> crate::Result<()> {
         file.write_all(&self.user_crc.to_le_bytes())?;
         file.write_all(&self.user_id.to_le_bytes())?;
         file.write_all(&self.last_read_msg.to_le_bytes())?;
@@ -0,0 +1,175 @@
+use std::{
+    fs::{File, OpenOptions},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use crate::util::file_lock::{lock_region, unlock_region};
+
+use super::{extensions, JamError, JamMessageBase};
+
+/// Interval between two lock attempts of `JamMessageBase::lock_timeout`
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// JAM locks the first byte of the .JHR file
+const LOCK_OFFSET: u64 = 0;
+const LOCK_LEN: u64 = 1;
+
+#[derive(Default)]
+pub(crate) struct LockState {
+    inner: Mutex<LockInner>,
+    /// Signaled when the lock gets released
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct LockInner {
+    /// The locked .JHR file
+    file: Option<File>,
+    /// The thread holding the lock
+    owner: Option<ThreadId>,
+    depth: u32,
+}
+
+impl LockState {
+    fn inner(&self) -> MutexGuard<'_, LockInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Wait {
+    Forever,
+    No,
+    Until(Instant),
+}
+
+/// Lock of a JAM message base, the lock is released when the guard is dropped.
+///
+/// # Remarks
+/// Locks taken through the same `JamMessageBase` are reentrant for the thread holding
+/// the lock, so it's safe to call `write_message` and friends while holding a lock.
+/// Other threads wait until the lock is released.
+pub struct JamLock {
+    state: Arc<LockState>,
+}
+
+impl Drop for JamLock {
+    fn drop(&mut self) {
+        let mut inner = self.state.inner();
+        inner.depth -= 1;
+        if inner.depth == 0 {
+            if let Some(file) = inner.file.take() {
+                if let Err(err) = unlock_region(&file, LOCK_OFFSET, LOCK_LEN) {
+                    log::error!("Error unlocking message base: {}", err);
+                }
+            }
+            inner.owner = None;
+            self.state.released.notify_all();
+        }
+    }
+}
+
+impl JamMessageBase {
+    /// Locks the message base, blocks until the lock is acquired.
+    ///
+    /// # Remarks
+    /// When an application needs to write to any of the message base files,
+    /// it must first attempt to lock the .JHR (header) file. As specified by JAM
+    /// only the first byte of the .JHR file is locked with an OS level byte range lock,
+    /// so other processes using this library (or any other application honoring the lock)
+    /// are excluded as well.
+    pub fn lock(&self) -> crate::Result<JamLock> {
+        let lock = self.acquire_lock(Wait::Forever)?;
+        Ok(lock.expect("blocking lock always succeeds"))
+    }
+
+    /// Tries to lock the message base, returns `None` if the lock is held by someone else.
+    pub fn try_lock(&self) -> crate::Result<Option<JamLock>> {
+        self.acquire_lock(Wait::No)
+    }
+
+    /// Tries to lock the message base until the timeout expires.
+    pub fn lock_timeout(&self, timeout: Duration) -> crate::Result<JamLock> {
+        let lock = self.acquire_lock(Wait::Until(Instant::now() + timeout))?;
+        lock.ok_or_else(|| JamError::LockTimeout.into())
+    }
+
+    fn acquire_lock(&self, wait: Wait) -> crate::Result<Option<JamLock>> {
+        let current = thread::current().id();
+        let mut inner = self.lock_state.inner();
+        while inner.owner.is_some_and(|owner| owner != current) {
+            inner = match wait {
+                Wait::Forever => self
+                    .lock_state
+                    .released
+                    .wait(inner)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Wait::No => return Ok(None),
+                Wait::Until(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    self.lock_state
+                        .released
+                        .wait_timeout(inner, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+
+        if inner.depth == 0 {
+            let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(header_file_name)?;
+            let locked = match wait {
+                Wait::Forever => lock_region(&file, LOCK_OFFSET, LOCK_LEN, true)?,
+                Wait::No => lock_region(&file, LOCK_OFFSET, LOCK_LEN, false)?,
+                Wait::Until(deadline) => loop {
+                    if lock_region(&file, LOCK_OFFSET, LOCK_LEN, false)? {
+                        break true;
+                    }
+                    if Instant::now() >= deadline {
+                        break false;
+                    }
+                    thread::sleep(LOCK_RETRY_INTERVAL);
+                },
+            };
+            if !locked {
+                return Ok(None);
+            }
+            inner.file = Some(file);
+            inner.owner = Some(current);
+        }
+        inner.depth += 1;
+        Ok(Some(JamLock {
+            state: self.lock_state.clone(),
+        }))
+    }
+
+    /// Runs `f` with a handle of the .JHR file.
+    ///
+    /// # Remarks
+    /// While the current thread holds the lock the locked handle is used - on Windows the
+    /// lock is mandatory and the locked byte can't be accessed through another handle.
+    /// Otherwise the file is opened read only, so writers need to hold the lock.
+    pub(crate) fn with_header_file<T>(
+        &self,
+        f: impl FnOnce(&mut File) -> crate::Result<T>,
+    ) -> crate::Result<T> {
+        let mut inner = self.lock_state.inner();
+        if inner.owner == Some(thread::current().id()) {
+            if let Some(file) = inner.file.as_mut() {
+                return f(file);
+            }
+        }
+        drop(inner);
+        let mut file = File::open(self.file_name.with_extension(extensions::HEADER_DATA))?;
+        f(&mut file)
+    }
+}
@@ -0,0 +1,197 @@
+use bstr::{BString, ByteSlice};
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::{
+    message_base::{flags, Message, MessageBase, MessageBaseFormat, MessageBaseWriter},
+    util::echmoail::EchomailAddress,
+};
+
+use super::{
+    attributes,
+    msg_header::{JamMessageHeader, MessageSubfield, SubfieldType},
+    JamError, JamMessage, JamMessageBase,
+};
+
+/// JAM attributes with a format neutral counterpart
+const FLAG_MAP: [(u32, u32); 8] = [
+    (attributes::MSG_PRIVATE, flags::PRIVATE),
+    (attributes::MSG_READ, flags::READ),
+    (attributes::MSG_DELETED, flags::DELETED),
+    (attributes::MSG_LOCAL, flags::LOCAL),
+    (attributes::MSG_SENT, flags::SENT),
+    (attributes::MSG_FILEATTACH, flags::FILE_ATTACH),
+    (attributes::MSG_RECEIPTREQ, flags::RECEIPT_REQUEST),
+    (attributes::MSG_LOCKED, flags::LOCKED),
+];
+
+/// Subfields that are stored as FTS kludge lines in other formats
+const KLUDGE_MAP: [(SubfieldType, &[u8]); 8] = [
+    (SubfieldType::MsgID, b"MSGID: "),
+    (SubfieldType::ReplyID, b"REPLY: "),
+    (SubfieldType::PID, b"PID: "),
+    (SubfieldType::Trace, b"Via "),
+    (SubfieldType::SeenBy2D, b"SEEN-BY: "),
+    (SubfieldType::Path2D, b"PATH: "),
+    (SubfieldType::Flags, b"FLAGS "),
+    (SubfieldType::TZUTCInfo, b"TZUTC: "),
+];
+
+impl MessageBase for JamMessageBase {
+    fn format(&self) -> MessageBaseFormat {
+        MessageBaseFormat::Jam
+    }
+
+    fn active_messages(&self) -> u32 {
+        JamMessageBase::active_messages(self)
+    }
+
+    fn get_message(&self, number: u32) -> crate::Result<Message> {
+        let (_, index) = self.read_index_record(number)?;
+        if index.is_unused() {
+            return Err(JamError::MessageDeleted.into());
+        }
+        let header = self.read_header_at(index.header_offset)?;
+        let text = self.read_msg_text(&header)?;
+        Ok(to_message(number, &header, &text))
+    }
+
+    fn messages(&self) -> Box<dyn Iterator<Item = crate::Result<Message>> + '_> {
+        match self.read_indexed_headers() {
+            Ok(headers) => Box::new(headers.into_iter().map(|(number, header)| {
+                let text = self.read_msg_text(&header)?;
+                Ok(to_message(number, &header, &text))
+            })),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+}
+
+impl MessageBaseWriter for JamMessageBase {
+    fn write_message(&mut self, msg: &Message) -> crate::Result<u32> {
+        JamMessageBase::write_message(self, &JamMessage::from(msg))
+    }
+
+    fn next_message_number(&self) -> crate::Result<u32> {
+        JamMessageBase::next_message_number(self)
+    }
+
+    fn set_next_message_number(&mut self, number: u32) -> crate::Result<()> {
+        JamMessageBase::set_next_message_number(self, number)
+    }
+}
+
+impl From<&Message> for JamMessage {
+    fn from(msg: &Message) -> Self {
+        let attributes = FLAG_MAP
+            .iter()
+            .filter(|(_, flag)| msg.flags & flag != 0)
+            .fold(0, |attributes, (attr, _)| attributes | attr);
+        let mut res = JamMessage::new(&msg.origin.unwrap_or_default())
+            .with_from(msg.from.clone())
+            .with_to(msg.to.clone())
+            .with_subject(msg.subject.clone())
+            .with_date_time(msg.date_written)
+            .with_reply_to(msg.reply_to)
+            .with_attributes(attributes)
+            .with_text(msg.text.replace("\n", "\r").into());
+        if !msg.password.is_empty() {
+            res = res.with_password(&msg.password);
+        }
+        if let Some(received) = msg.date_received {
+            res.header.date_received = received.and_utc().timestamp() as u32;
+        }
+        if let Some(origin) = msg.origin {
+            res.header.sub_fields.push(MessageSubfield::new(
+                SubfieldType::Address0,
+                origin.to_string().into(),
+            ));
+        }
+        for file in &msg.attachments {
+            res.header
+                .sub_fields
+                .push(MessageSubfield::new(SubfieldType::EnclFile, file.clone()));
+        }
+        for kludge in &msg.kludges {
+            let (field_type, content) = kludge_to_subfield(kludge);
+            match field_type {
+                // keep the original ids instead of the generated ones
+                SubfieldType::MsgID => {
+                    res.header.msgid_crc = JamMessageBase::get_crc(&content);
+                    res.set_subfield(field_type, content);
+                }
+                SubfieldType::ReplyID => {
+                    res.header.replycrc = JamMessageBase::get_crc(&content);
+                    res.set_subfield(field_type, content);
+                }
+                _ => res
+                    .header
+                    .sub_fields
+                    .push(MessageSubfield::new(field_type, content)),
+            }
+        }
+        res
+    }
+}
+
+fn to_message(number: u32, header: &JamMessageHeader, text: &[u8]) -> Message {
+    let mut msg = Message {
+        number,
+        date_written: from_unix_time(header.date_written),
+        date_received: (header.date_received != 0).then(|| from_unix_time(header.date_received)),
+        flags: FLAG_MAP
+            .iter()
+            .filter(|(attr, _)| header.attributes & attr != 0)
+            .fold(0, |flags, (_, flag)| flags | flag),
+        reply_to: header.reply_to,
+        // JAM uses CR as line separator, LF may be used by some writers.
+        text: text.replace("\r\n", "\n").replace("\r", "\n").into(),
+        ..Default::default()
+    };
+    for sf in &header.sub_fields {
+        let content = sf.get_string().clone();
+        match sf.get_type() {
+            SubfieldType::SenderName => msg.from = content,
+            SubfieldType::RecvName => msg.to = content,
+            SubfieldType::Subject => msg.subject = content,
+            SubfieldType::EnclFile | SubfieldType::EnclFieleWc => msg.attachments.push(content),
+            SubfieldType::Address0 => {
+                if msg.origin.is_none() {
+                    msg.origin = EchomailAddress::parse(&content.to_str_lossy());
+                }
+            }
+            _ => {
+                if let Some(kludge) = subfield_to_kludge(sf) {
+                    msg.kludges.push(kludge);
+                }
+            }
+        }
+    }
+    msg
+}
+
+fn from_unix_time(time: u32) -> NaiveDateTime {
+    DateTime::from_timestamp(time as i64, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// Converts a subfield to the FTS kludge line it was extracted from.
+fn subfield_to_kludge(sf: &MessageSubfield) -> Option<BString> {
+    let prefix: &[u8] = match sf.get_type() {
+        SubfieldType::FTSKludge => b"",
+        field_type => KLUDGE_MAP.iter().find(|(t, _)| t == field_type)?.1,
+    };
+    let mut kludge = BString::from(prefix);
+    kludge.extend_from_slice(sf.get_string());
+    Some(kludge)
+}
+
+/// Extracts the subfield data from a FTS kludge line.
+fn kludge_to_subfield(kludge: &[u8]) -> (SubfieldType, BString) {
+    for (field_type, prefix) in KLUDGE_MAP {
+        if let Some(content) = kludge.strip_prefix(prefix) {
+            return (field_type, content.trim().into());
+        }
+    }
+    (SubfieldType::FTSKludge, kludge.trim().into())
+}
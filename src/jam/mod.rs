@@ -1,7 +1,8 @@
+use std::collections::HashSet;
 use std::fs::{self, OpenOptions};
 use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fs::File, io::Read};
 
@@ -14,13 +15,23 @@ use thiserror::Error;
 use crate::util::crc32::{self, CRC_SEED};
 use crate::util::echmoail::EchomailAddress;
 
+use self::jdx_record::JamIndexRecord;
 use self::jhr_header::JHRHeaderInfo;
 use self::last_read_storage::JamLastReadStorage;
+use self::lock::LockState;
 use self::msg_header::{JamMessageHeader, MessageSubfield, SubfieldType};
 
+pub mod check;
+pub mod jdx_record;
 pub mod jhr_header;
 pub mod last_read_storage;
+pub mod lock;
+mod message_base;
 pub mod msg_header;
+pub mod pack;
+pub mod purge;
+pub mod search;
+pub mod threads;
 
 #[cfg(test)]
 mod tests;
@@ -30,6 +41,12 @@ pub enum JamError {
     #[error("Invalid header signature (needs to start with 'JAM\\0')")]
     InvalidHeaderSignature,
 
+    #[error("Subfield length {0} exceeds the header file")]
+    SubfieldDataOutOfRange(u32),
+
+    #[error("Message text (offset {0}, length {1}) exceeds the text file")]
+    TextOutOfRange(u32, u32),
+
     #[error("Index file corrupted")]
     IndexFileCorrupted,
 
@@ -47,6 +64,15 @@ pub enum JamError {
 
     #[error("Index file corrupt at record {0} (file length: {1})")]
     IndexFileCorrupt(u64, u64),
+
+    #[error("Timeout while waiting for the message base lock")]
+    LockTimeout,
+
+    #[error("Message {0} is locked")]
+    MessageLocked(u32),
+
+    #[error("Message number {0} is already used, the next free number is {1}")]
+    MessageNumberInUse(u32, u32),
 }
 
 mod extensions {
@@ -69,7 +95,7 @@ pub struct JamMessageBase {
     file_name: PathBuf,
     header_info: JHRHeaderInfo,
     last_read_record: i32,
-    locked: AtomicBool,
+    lock_state: Arc<LockState>,
 }
 
 impl JamMessageBase {
@@ -81,7 +107,7 @@ impl JamMessageBase {
             file_name: file_name.as_ref().into(),
             header_info,
             last_read_record: -1,
-            locked: AtomicBool::new(false),
+            lock_state: Arc::default(),
         })
     }
 
@@ -168,22 +194,6 @@ impl JamMessageBase {
         Ok(())
     }
 
-    /// Locks the message base.
-    /// User is responsible for locking/unlocking.
-    ///
-    /// Note that locking is just process only.
-    pub fn lock(&self) {
-        while self.locked.swap(true, std::sync::atomic::Ordering::Acquire) {
-            std::hint::spin_loop();
-        }
-    }
-
-    /// Unlocks the message base
-    pub fn unlock(&self) {
-        self.locked
-            .store(false, std::sync::atomic::Ordering::Release);
-    }
-
     /// Get the jam base crc of a string
     ///
     /// This is the lowercase z-modem crc32
@@ -194,60 +204,177 @@ impl JamMessageBase {
         crc ^ CRC_SEED
     }
 
-    pub fn write_message(&mut self, message: &JamMessage) -> crate::Result<()> {
+    /// Appends a message to the message base and returns the assigned message number.
+    ///
+    /// # Remarks
+    /// The message number is the next free number (BaseMsgNum + number of index records).
+    /// DateReceived and DateProcessed are set to the current time if they're not set.
+    /// The .JHR header info gets updated.
+    ///
+    /// If the message is a reply it's appended to the reply chain of the original message
+    /// and the REPLY crc/subfield is taken from the MSGID of the original message.
+    pub fn write_message(&mut self, message: &JamMessage) -> crate::Result<u32> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let msg_number = self.header_info.base_msg_num + self.index_record_count()?;
+
         let mut header = message.create_jam_header();
+        header.message_number = msg_number;
+        let now = unix_time_now();
+        if header.date_received == 0 {
+            header.date_received = now;
+        }
+        if header.date_processed == 0 {
+            header.date_processed = now;
+        }
+        let reply_link = if header.reply_to != 0 && header.reply_to != msg_number {
+            self.link_reply(&mut header, msg_number)?
+        } else {
+            None
+        };
+
         let text_file_name = self.file_name.with_extension(extensions::TEXT_DATA);
         let mut text_file = OpenOptions::new().append(true).open(text_file_name)?;
-
-        self.header_info.active_msgs += 1;
-
         header.offset = text_file.metadata()?.len() as u32;
         header.txt_len = message.get_text().len() as u32;
         text_file.write_all(message.get_text())?;
 
-        let header_path = self.file_name.with_extension(extensions::HEADER_DATA);
-        let header_file = OpenOptions::new().append(true).open(header_path)?;
-        let message_header_offset = header_file.metadata()?.len() as u32;
-
-        let mut writer = BufWriter::new(header_file);
-        header.write(&mut writer)?;
-        writer.flush()?;
+        let message_header_offset = self.append_header(&header)?;
 
         let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
         let mut index_file = OpenOptions::new().append(true).open(index_file_name)?;
-        let crc = if let Some(to) = header.get_to() {
-            Self::get_crc(to)
-        } else {
-            CRC_SEED
+        JamIndexRecord::new(header.get_to_crc(), message_header_offset).write(&mut index_file)?;
+
+        // the chain is only linked once the new message exists
+        if let Some((offset, linked_header)) = reply_link {
+            self.write_header_at(offset, &linked_header)?;
+        }
+        if !header.is_deleted() {
+            self.header_info.active_msgs += 1;
+        }
+        self.write_jhr_header()?;
+        Ok(msg_number)
+    }
+
+    /// Appends a new reply to the reply chain of the message it's replying to.
+    ///
+    /// The original message gets the reply as Reply1st or the last reply
+    /// in the chain gets it as ReplyNext. Returns the header that needs to be
+    /// written (and its offset), `None` if the original message doesn't exist.
+    fn link_reply(
+        &self,
+        header: &mut JamMessageHeader,
+        msg_number: u32,
+    ) -> crate::Result<Option<(u32, JamMessageHeader)>> {
+        let parent_index = match self.read_index_record(header.reply_to) {
+            Ok((_, index)) if !index.is_unused() => index,
+            _ => {
+                log::warn!(
+                    "Message {} replies to unknown message {}",
+                    msg_number,
+                    header.reply_to
+                );
+                return Ok(None);
+            }
         };
-        index_file.write_all(&crc.to_le_bytes())?;
-        index_file.write_all(&message_header_offset.to_le_bytes())?;
+        let parent = self.read_header_at(parent_index.header_offset)?;
+        if header.replycrc == CRC_SEED || header.replycrc == 0 {
+            header.replycrc = parent.msgid_crc;
+        }
+        if header.get_reply_id().is_none() {
+            if let Some(msgid) = parent.get_msgid() {
+                header
+                    .sub_fields
+                    .push(MessageSubfield::new(SubfieldType::ReplyID, msgid.clone()));
+            }
+        }
+
+        // (header offset, header, is the original message)
+        let mut last = (parent_index.header_offset, parent, true);
+        let mut next = last.1.reply1st;
+        let mut visited = HashSet::new();
+        while next != 0 && visited.insert(next) {
+            match self.read_index_record(next) {
+                Ok((_, index)) if !index.is_unused() => {
+                    let sibling = self.read_header_at(index.header_offset)?;
+                    next = sibling.replynext;
+                    last = (index.header_offset, sibling, false);
+                }
+                // broken chain - the link gets replaced by the new reply
+                _ => break,
+            }
+        }
+
+        let (offset, mut last_header, is_parent) = last;
+        if is_parent {
+            last_header.reply1st = msg_number;
+        } else {
+            last_header.replynext = msg_number;
+        }
+        Ok(Some((offset, last_header)))
+    }
+
+    /// The number `write_message` assigns to the next message.
+    pub fn next_message_number(&self) -> crate::Result<u32> {
+        Ok(self.header_info.base_msg_num + self.index_record_count()?)
+    }
+
+    /// Skips message numbers so the next written message gets `msg_number`.
+    ///
+    /// # Remarks
+    /// For an empty message base BaseMsgNum is changed, otherwise unused (-1)
+    /// index records are appended for the skipped numbers.
+    pub fn set_next_message_number(&mut self, msg_number: u32) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let records = self.index_record_count()?;
+        if records == 0 {
+            self.header_info.base_msg_num = msg_number;
+            return self.write_jhr_header();
+        }
+        let next = self.header_info.base_msg_num + records;
+        if msg_number < next {
+            return Err(JamError::MessageNumberInUse(msg_number, next).into());
+        }
+        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
+        let mut writer = BufWriter::new(OpenOptions::new().append(true).open(index_file_name)?);
+        for _ in next..msg_number {
+            JamIndexRecord::UNUSED.write(&mut writer)?;
+        }
+        writer.flush()?;
         Ok(())
     }
 
     /// Writes the current header to disk.
     pub fn write_jhr_header(&mut self) -> crate::Result<()> {
-        let header_path = self.file_name.with_extension(extensions::HEADER_DATA);
-        let header_file = OpenOptions::new().write(true).open(header_path)?;
-        let mut writer = BufWriter::new(header_file);
-        println!("header info : {} ", self.header_info.active_msgs);
-        self.header_info.update(&mut writer)?;
-        writer.flush()?;
+        let _lock = self.lock()?;
+        let mut header_info = self.header_info.clone();
+        self.with_header_file(|file| {
+            let mut writer = BufWriter::new(file);
+            header_info.update(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })?;
+        self.header_info = header_info;
         Ok(())
     }
 
     /// Updates header with the one from disk.
     /// Usually it's not required to call that (only for outside changes detected)
     pub fn read_jhr_header(&mut self) -> crate::Result<()> {
-        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
-        let mut header = File::open(header_file_name)?;
-        self.header_info = JHRHeaderInfo::load(&mut header)?;
+        self.header_info = self.with_header_file(|file| {
+            file.seek(SeekFrom::Start(0))?;
+            JHRHeaderInfo::load(file)
+        })?;
         Ok(())
     }
 
     pub fn read_msg_text(&self, header: &JamMessageHeader) -> crate::Result<BString> {
         let text_file_name = self.file_name.with_extension(extensions::TEXT_DATA);
         let mut text_file = File::open(text_file_name)?;
+        if header.offset as u64 + header.txt_len as u64 > text_file.metadata()?.len() {
+            return Err(JamError::TextOutOfRange(header.offset, header.txt_len).into());
+        }
         text_file.seek(SeekFrom::Start(header.offset as u64))?;
         let mut buffer = vec![0; header.txt_len as usize];
         text_file.read_exact(&mut buffer)?;
@@ -266,34 +393,122 @@ impl JamMessageBase {
         Ok(res)
     }
 
-    pub fn read_header(&self, msg_number: u32) -> crate::Result<JamMessageHeader> {
-        if msg_number < self.header_info.base_msg_num || msg_number > self.header_info.active_msgs {
+    /// Highest message number in the index file.
+    ///
+    /// For an empty message base that's `base_messagenumber() - 1`.
+    pub fn highest_messagenumber(&self) -> crate::Result<u32> {
+        let records = self.index_record_count()?;
+        Ok((self.header_info.base_msg_num + records).saturating_sub(1))
+    }
+
+    fn index_record_count(&self) -> crate::Result<u32> {
+        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
+        let len = fs::metadata(index_file_name)?.len();
+        Ok((len / JamIndexRecord::RECORD_SIZE) as u32)
+    }
+
+    /// Reads all records of the .JDX file.
+    pub fn read_index(&self) -> crate::Result<Vec<JamIndexRecord>> {
+        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
+        let index_file = fs::read(index_file_name)?;
+        if index_file.len() as u64 % JamIndexRecord::RECORD_SIZE != 0 {
+            return Err(Box::new(JamError::IndexFileCorrupted));
+        }
+        let mut data = &index_file[..];
+        let mut res = Vec::with_capacity(index_file.len() / JamIndexRecord::RECORD_SIZE as usize);
+        while !data.is_empty() {
+            res.push(JamIndexRecord::load(&mut data)?);
+        }
+        Ok(res)
+    }
+
+    /// Gets the .JDX record number & record for a given message number.
+    fn read_index_record(&self, msg_number: u32) -> crate::Result<(u64, JamIndexRecord)> {
+        let highest = self.highest_messagenumber()?;
+        if msg_number < self.header_info.base_msg_num || msg_number > highest {
             return Err(JamError::MessageNumberOutOfRange(
                 msg_number,
                 self.header_info.base_msg_num,
-                self.header_info.active_msgs,
+                highest,
             )
             .into());
         }
         let record = (msg_number - self.header_info.base_msg_num) as u64;
 
         let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
-        let mut index_file = OpenOptions::new().read(true).open(index_file_name)?;
-        if let Err(_err) = index_file.seek(SeekFrom::Start(record * 8 + 4)) {
+        let mut index_file = File::open(index_file_name)?;
+        if let Err(_err) = index_file.seek(SeekFrom::Start(record * JamIndexRecord::RECORD_SIZE)) {
             return Err(JamError::IndexFileCorrupt(record, index_file.metadata()?.len()).into());
         }
-        let mut offset = [0; 4];
-        if let Err(err) = index_file.read_exact(&mut offset) {
-            log::error!("Error reading index file: {}", err);
-            return Err(JamError::IndexFileCorrupt(record, index_file.metadata()?.len()).into());
+        match JamIndexRecord::load(&mut index_file) {
+            Ok(index) => Ok((record, index)),
+            Err(err) => {
+                log::error!("Error reading index file: {}", err);
+                Err(JamError::IndexFileCorrupt(record, index_file.metadata()?.len()).into())
+            }
         }
-        let offset = u32::from_le_bytes(offset);
+    }
 
+    pub(crate) fn read_header_at(&self, offset: u32) -> crate::Result<JamMessageHeader> {
         let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
         let mut header_file = File::open(header_file_name)?;
         header_file.seek(SeekFrom::Start(offset as u64))?;
         let mut reader = BufReader::new(header_file);
-        let header = JamMessageHeader::read(&mut reader)?;
+        JamMessageHeader::read(&mut reader)
+    }
+
+    /// Overwrites a header in place - the subfield length must not change.
+    pub(crate) fn write_header_at(
+        &self,
+        offset: u32,
+        header: &JamMessageHeader,
+    ) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        self.with_header_file(|file| {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            let mut writer = BufWriter::new(file);
+            header.write(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })
+    }
+
+    /// Appends a header to the .JHR file and returns its offset.
+    fn append_header(&self, header: &JamMessageHeader) -> crate::Result<u32> {
+        let _lock = self.lock()?;
+        self.with_header_file(|file| {
+            let offset = file.seek(SeekFrom::End(0))? as u32;
+            let mut writer = BufWriter::new(file);
+            header.write(&mut writer)?;
+            writer.flush()?;
+            Ok(offset)
+        })
+    }
+
+    /// Reads the headers of all messages in the index file (including deleted ones)
+    /// together with their message number.
+    pub(crate) fn read_indexed_headers(&self) -> crate::Result<Vec<(u32, JamMessageHeader)>> {
+        let index = self.read_index()?;
+        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
+        let mut reader = BufReader::new(File::open(header_file_name)?);
+        let mut res = Vec::with_capacity(index.len());
+        for (i, record) in index.iter().enumerate() {
+            if record.is_unused() {
+                continue;
+            }
+            reader.seek(SeekFrom::Start(record.header_offset as u64))?;
+            let header = JamMessageHeader::read(&mut reader)?;
+            res.push((self.header_info.base_msg_num + i as u32, header));
+        }
+        Ok(res)
+    }
+
+    pub fn read_header(&self, msg_number: u32) -> crate::Result<JamMessageHeader> {
+        let (_, index) = self.read_index_record(msg_number)?;
+        if index.is_unused() {
+            return Err(JamError::MessageDeleted.into());
+        }
+        let header = self.read_header_at(index.header_offset)?;
         log::info!("Read {} deleted {}", msg_number, header.is_deleted());
 
         if header.is_deleted() {
@@ -305,76 +520,117 @@ impl JamMessageBase {
     /// Sets the delete flag of a given message header
     /// `read_header` will never return a deleted message. But it's still there and can be recovered.
     /// The message will be deleted when the message base gets packed.
-    pub fn delete_message(&self, msg_number: u32) -> crate::Result<()> {
-        if msg_number < self.header_info.base_msg_num || msg_number > self.header_info.active_msgs {
-            return Err(JamError::MessageNumberOutOfRange(
-                msg_number,
-                self.header_info.base_msg_num,
-                self.header_info.active_msgs,
-            )
-            .into());
+    pub fn delete_message(&mut self, msg_number: u32) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let (_, index) = self.read_index_record(msg_number)?;
+        if index.is_unused() {
+            return Ok(());
         }
-        let record = (msg_number - self.header_info.base_msg_num) as u64;
-        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
-        let mut index_file = OpenOptions::new().read(true).open(index_file_name)?;
-        if index_file.seek(SeekFrom::Start(record * 8 + 4)).is_err() {
-            return Err(JamError::IndexFileCorrupt(record, index_file.metadata()?.len()).into());
-        }
-        let mut offset = [0; 4];
-        index_file.read_exact(&mut offset)?;
-        let offset = u32::from_le_bytes(offset);
-        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
-        let mut header_file = File::open(&header_file_name)?;
-        header_file.seek(SeekFrom::Start(offset as u64))?;
-        let mut reader = BufReader::new(header_file);
-        let mut header = JamMessageHeader::read(&mut reader)?;
+        let mut header = self.read_header_at(index.header_offset)?;
         if !header.is_deleted() {
             header.attributes |= attributes::MSG_DELETED;
-            let mut header_file = OpenOptions::new().write(true).open(header_file_name)?;
-            header_file.seek(SeekFrom::Start(offset as u64))?;
-            let mut writer = BufWriter::new(header_file);
-            header.write(&mut writer)?;
+            self.write_header_at(index.header_offset, &header)?;
             log::info!("Message {} deleted {}", msg_number, header.is_deleted());
-            writer.flush()?;
+            self.header_info.active_msgs = self.header_info.active_msgs.saturating_sub(1);
+            self.write_jhr_header()?;
         }
         Ok(())
     }
 
     /// Recovers a deleted message
     /// The opposite of `delete_message`
-    pub fn restore_message(&self, msg_number: u32) -> crate::Result<()> {
-        if msg_number < self.header_info.base_msg_num || msg_number > self.header_info.active_msgs {
-            return Err(JamError::MessageNumberOutOfRange(
-                msg_number,
-                self.header_info.base_msg_num,
-                self.header_info.active_msgs,
-            )
-            .into());
-        }
-        let record = (msg_number - self.header_info.base_msg_num) as u64;
-        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
-        let mut index_file = OpenOptions::new().read(true).open(index_file_name)?;
-        if index_file.seek(SeekFrom::Start(record * 8 + 4)).is_err() {
-            return Err(JamError::IndexFileCorrupt(record, index_file.metadata()?.len()).into());
+    pub fn restore_message(&mut self, msg_number: u32) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let (_, index) = self.read_index_record(msg_number)?;
+        if index.is_unused() {
+            return Err(JamError::MessageDeleted.into());
         }
-        let mut offset = [0; 4];
-        index_file.read_exact(&mut offset)?;
-        let offset = u32::from_le_bytes(offset);
-        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
-        let mut header_file = File::open(&header_file_name)?;
-        header_file.seek(SeekFrom::Start(offset as u64))?;
-        let mut reader = BufReader::new(header_file);
-        let mut header = JamMessageHeader::read(&mut reader)?;
+        let mut header = self.read_header_at(index.header_offset)?;
         if header.is_deleted() {
             header.attributes &= !attributes::MSG_DELETED;
-            let mut header_file = OpenOptions::new().write(true).open(header_file_name)?;
-            header_file.seek(SeekFrom::Start(offset as u64))?;
-            let mut writer = BufWriter::new(header_file);
-            header.write(&mut writer)?;
+            self.write_header_at(index.header_offset, &header)?;
+            self.header_info.active_msgs += 1;
+            self.write_jhr_header()?;
         }
         Ok(())
     }
 
+    /// Reads header & text of a message
+    pub fn read_message(&self, msg_number: u32) -> crate::Result<JamMessage> {
+        let header = self.read_header(msg_number)?;
+        let text = self.read_msg_text(&header)?;
+        Ok(JamMessage::from_header(header, text))
+    }
+
+    /// Replaces header & text of an existing message.
+    ///
+    /// # Remarks
+    /// The message number and the reply chain (Reply1st, ReplyNext) of the existing
+    /// message are kept. If the size of the subfields didn't change the header is rewritten
+    /// in place, otherwise the new header is appended and the old one is marked as deleted.
+    /// Changed text is appended to the .JDT file. The old data is reclaimed by `pack`.
+    ///
+    /// Messages with the MSG_LOCKED attribute can't be edited.
+    pub fn update_message(&mut self, msg_number: u32, message: &JamMessage) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let (record, index) = self.read_index_record(msg_number)?;
+        if index.is_unused() {
+            return Err(JamError::MessageDeleted.into());
+        }
+        let mut old_header = self.read_header_at(index.header_offset)?;
+        if old_header.attributes & attributes::MSG_LOCKED != 0 {
+            return Err(JamError::MessageLocked(msg_number).into());
+        }
+
+        let old_deleted = old_header.is_deleted();
+        let mut header = message.create_jam_header();
+        header.message_number = msg_number;
+        header.reply1st = old_header.reply1st;
+        header.replynext = old_header.replynext;
+
+        if self.read_msg_text(&old_header)? == *message.get_text() {
+            header.offset = old_header.offset;
+            header.txt_len = old_header.txt_len;
+        } else {
+            let text_file_name = self.file_name.with_extension(extensions::TEXT_DATA);
+            let mut text_file = OpenOptions::new().append(true).open(text_file_name)?;
+            header.offset = text_file.metadata()?.len() as u32;
+            header.txt_len = message.get_text().len() as u32;
+            text_file.write_all(message.get_text())?;
+        }
+
+        let header_offset = if header.subfield_len() == old_header.subfield_len() {
+            self.write_header_at(index.header_offset, &header)?;
+            index.header_offset
+        } else {
+            let header_offset = self.append_header(&header)?;
+
+            // The old header must be changed to indicate that it has been deleted
+            // and the text length set to zero (the text may be used by the new header).
+            old_header.attributes |= attributes::MSG_DELETED;
+            old_header.txt_len = 0;
+            self.write_header_at(index.header_offset, &old_header)?;
+            header_offset
+        };
+
+        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
+        let mut index_file = OpenOptions::new().write(true).open(index_file_name)?;
+        index_file.seek(SeekFrom::Start(record * JamIndexRecord::RECORD_SIZE))?;
+        JamIndexRecord::new(header.get_to_crc(), header_offset).write(&mut index_file)?;
+
+        if header.is_deleted() != old_deleted {
+            if header.is_deleted() {
+                self.header_info.active_msgs = self.header_info.active_msgs.saturating_sub(1);
+            } else {
+                self.header_info.active_msgs += 1;
+            }
+        }
+        self.write_jhr_header()
+    }
+
     pub fn read_last_read_file(&self) -> crate::Result<Vec<JamLastReadStorage>> {
         let last_read_file_name = self.file_name.with_extension(extensions::LASTREAD_INFO);
         let last_read_file = File::open(last_read_file_name)?;
@@ -428,6 +684,145 @@ impl JamMessageBase {
         Ok(None)
     }
 
+    /// Stores the last read information of a user.
+    ///
+    /// # Remarks
+    /// An existing record of the user is updated in place. Otherwise a deleted
+    /// record gets reused or a new record is appended.
+    pub fn set_last_read(
+        &mut self,
+        user_name_crc: u32,
+        id: u32,
+        last_read_msg: u32,
+        high_read_msg: u32,
+    ) -> crate::Result<()> {
+        let _lock = self.lock()?;
+        let last_read_file_name = self.file_name.with_extension(extensions::LASTREAD_INFO);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(last_read_file_name)?;
+        let is_user = |lr: &JamLastReadStorage| lr.user_crc == user_name_crc && lr.user_id == id;
+
+        // find_last_read remembers the record of the last user
+        let mut record = None;
+        if self.last_read_record >= 0 {
+            file.seek(SeekFrom::Start(
+                self.last_read_record as u64 * JamLastReadStorage::LAST_READ_SIZE as u64,
+            ))?;
+            if JamLastReadStorage::load(&mut file).is_ok_and(|lr| is_user(&lr)) {
+                record = Some(self.last_read_record as usize);
+            }
+        }
+        let record = match record {
+            Some(record) => record,
+            None => {
+                file.seek(SeekFrom::Start(0))?;
+                let mut reader = BufReader::new(&mut file);
+                let mut records = Vec::new();
+                while let Ok(last_read) = JamLastReadStorage::load(&mut reader) {
+                    records.push(last_read);
+                }
+                records
+                    .iter()
+                    .position(is_user)
+                    .or_else(|| records.iter().position(JamLastReadStorage::is_deleted))
+                    .unwrap_or(records.len())
+            }
+        };
+
+        file.seek(SeekFrom::Start(
+            record as u64 * JamLastReadStorage::LAST_READ_SIZE as u64,
+        ))?;
+        JamLastReadStorage {
+            user_crc: user_name_crc,
+            user_id: id,
+            last_read_msg,
+            high_read_msg,
+        }
+        .write(&mut file)?;
+        self.last_read_record = record as i32;
+        Ok(())
+    }
+
+    /// Marks the last read record of a user as deleted.
+    /// Returns false if the user doesn't have a last read record.
+    pub fn remove_last_read(&mut self, user_name_crc: u32, id: u32) -> crate::Result<bool> {
+        let _lock = self.lock()?;
+        let records = self.read_last_read_file()?;
+        let Some(record) = records
+            .iter()
+            .position(|lr| lr.user_crc == user_name_crc && lr.user_id == id)
+        else {
+            return Ok(false);
+        };
+        let last_read_file_name = self.file_name.with_extension(extensions::LASTREAD_INFO);
+        let mut file = OpenOptions::new().write(true).open(last_read_file_name)?;
+        file.seek(SeekFrom::Start(
+            record as u64 * JamLastReadStorage::LAST_READ_SIZE as u64,
+        ))?;
+        JamLastReadStorage {
+            user_crc: u32::MAX,
+            user_id: u32::MAX,
+            ..Default::default()
+        }
+        .write(&mut file)?;
+        self.last_read_record = -1;
+        Ok(true)
+    }
+
+    /// Rewrites the last read file with the records `keep` returns true for.
+    /// Deleted records are removed as well.
+    /// Returns the number of removed records.
+    pub fn retain_last_read<F: FnMut(&JamLastReadStorage) -> bool>(
+        &mut self,
+        mut keep: F,
+    ) -> crate::Result<usize> {
+        let _lock = self.lock()?;
+        let records = self.read_last_read_file()?;
+        let total = records.len();
+        let last_read_file_name = self.file_name.with_extension(extensions::LASTREAD_INFO);
+        let mut writer = BufWriter::new(File::create(last_read_file_name)?);
+        let mut kept = 0;
+        for last_read in records {
+            if !last_read.is_deleted() && keep(&last_read) {
+                last_read.write(&mut writer)?;
+                kept += 1;
+            }
+        }
+        writer.flush()?;
+        self.last_read_record = -1;
+        Ok(total - kept)
+    }
+
+    /// Number of active messages after the last read message of a user.
+    /// Users without last read record haven't read any message.
+    pub fn unread_count(&mut self, user_name_crc: u32, id: u32) -> crate::Result<u32> {
+        self.read_jhr_header()?;
+        let last_read_msg = self
+            .read_last_read_file()?
+            .into_iter()
+            .find(|lr| lr.user_crc == user_name_crc && lr.user_id == id)
+            .map_or(0, |lr| lr.last_read_msg);
+        let base = self.header_info.base_msg_num;
+        let first_unread = last_read_msg.saturating_add(1).max(base);
+
+        let index = self.read_index()?;
+        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
+        let mut reader = BufReader::new(File::open(header_file_name)?);
+        let mut unread = 0;
+        for record in index.iter().skip((first_unread - base) as usize) {
+            if record.is_unused() {
+                continue;
+            }
+            reader.seek(SeekFrom::Start(record.header_offset as u64))?;
+            if !JamMessageHeader::read(&mut reader)?.is_deleted() {
+                unread += 1;
+            }
+        }
+        Ok(unread)
+    }
+
     /// Gixes back all the record number (+BaseMsgNum) within the .JDX file determines a message's number for a given user.
     pub fn search_message_index(&self, crc: u32) -> crate::Result<Vec<u32>> {
         let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
@@ -438,17 +833,14 @@ impl JamMessageBase {
         }
         // all indices need to be scanned so it can be done in parallel
         let needle = crc.to_le_bytes();
+        let base = self.header_info.base_msg_num;
         let res = (0..index_file.len() / 8)
             .into_par_iter()
             .filter(|o| {
                 let i = o << 3;
                 index_file[i..].starts_with(&needle)
             })
-            .map(|i| {
-                let data = &index_file[i + 4..];
-                convert_single_u32!(msg_num, data);
-                msg_num
-            })
+            .map(|o| base + o as u32)
             .collect();
         Ok(res)
     }
@@ -487,6 +879,13 @@ impl JamMessageBase {
     }
 }
 
+fn unix_time_now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs() as u32)
+        .unwrap_or_default()
+}
+
 struct JamBaseMessageIter {
     reader: BufReader<File>,
     size: u64,
@@ -510,28 +909,31 @@ impl Iterator for JamBaseMessageIter {
 /// Used for writing messages to a JAM message base
 /// It's more complex to create a valid jam message than it looks.
 /// Using the builder pattern is recommended.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct JamMessage {
     header: JamMessageHeader,
     text: BString,
 }
 
 impl JamMessage {
-    pub fn get_msg_number(&self) -> u32 {
-        self.header.message_number
-    }
     pub fn get_msgid_crc(&self) -> u32 {
         self.header.msgid_crc
     }
 
+    /// Creates a message from an existing header, used for editing messages.
+    pub fn from_header(header: JamMessageHeader, text: BString) -> Self {
+        JamMessage { header, text }
+    }
+
+    pub fn get_header(&self) -> &JamMessageHeader {
+        &self.header
+    }
+
     /// Creates a new message with an unique message id
-    pub fn new(msg_number: u32, aka: &EchomailAddress) -> Self {
-        let now = SystemTime::now();
-        let date_written = if let Ok(unix_time) = now.duration_since(UNIX_EPOCH) {
-            unix_time.as_secs() as u32
-        } else {
-            0
-        };
+    ///
+    /// The message number is assigned by `JamMessageBase::write_message`.
+    pub fn new(aka: &EchomailAddress) -> Self {
+        let date_written = unix_time_now();
 
         let rnd: u32 = random();
         let id = BString::from(format!("{} {:08x}", aka, rnd));
@@ -539,7 +941,6 @@ impl JamMessage {
 
         JamMessage {
             header: JamMessageHeader {
-                message_number: msg_number,
                 msgid_crc,
                 date_written,
                 sub_fields: vec![MessageSubfield::new(SubfieldType::MsgID, id)],
@@ -594,6 +995,35 @@ impl JamMessage {
         self
     }
 
+    /// Replaces the sender name, used to edit messages read from a message base.
+    pub fn set_from(&mut self, name: BString) {
+        self.set_subfield(SubfieldType::SenderName, name);
+    }
+
+    /// Replaces the recipient name, used to edit messages read from a message base.
+    pub fn set_to(&mut self, name: BString) {
+        self.set_subfield(SubfieldType::RecvName, name);
+    }
+
+    /// Replaces the subject, used to edit messages read from a message base.
+    pub fn set_subject(&mut self, subject: BString) {
+        self.set_subfield(SubfieldType::Subject, subject);
+    }
+
+    /// Replaces the first subfield of the given type or adds a new one.
+    fn set_subfield(&mut self, field_type: SubfieldType, content: BString) {
+        let field = MessageSubfield::new(field_type, content);
+        match self
+            .header
+            .sub_fields
+            .iter_mut()
+            .find(|sf| *sf.get_type() == field_type)
+        {
+            Some(sf) => *sf = field,
+            None => self.header.sub_fields.push(field),
+        }
+    }
+
     pub fn with_is_deleted(mut self, deleted: bool) -> Self {
         if deleted {
             self.header.attributes |= attributes::MSG_DELETED;
@@ -1,7 +1,4 @@
-use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Read, Write},
-};
+use std::io::{Read, Write};
 
 use bstr::BString;
 
@@ -12,7 +9,7 @@ use crate::{
 
 use super::{attributes, JamMessageBase};
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct JamMessageHeader {
     /// <J><A><M> followed by <NUL>
     //pub signature: u32,
@@ -153,6 +150,33 @@ impl JamMessageHeader {
         None
     }
 
+    /// CRC-32 of the recipient's name (lowercase) as used in the .JDX file
+    pub fn get_to_crc(&self) -> u32 {
+        if let Some(to) = self.get_to() {
+            JamMessageBase::get_crc(to)
+        } else {
+            CRC_SEED
+        }
+    }
+
+    pub fn get_msgid(&self) -> Option<&BString> {
+        for s in &self.sub_fields {
+            if s.get_type() == &SubfieldType::MsgID {
+                return Some(&s.content);
+            }
+        }
+        None
+    }
+
+    pub fn get_reply_id(&self) -> Option<&BString> {
+        for s in &self.sub_fields {
+            if s.get_type() == &SubfieldType::ReplyID {
+                return Some(&s.content);
+            }
+        }
+        None
+    }
+
     /// True, if a password is required to access this msg base
     pub fn needs_password(&self) -> bool {
         self.password_crc != CRC_SEED
@@ -165,7 +189,7 @@ impl JamMessageHeader {
                 == JamMessageBase::get_crc(&BString::new(password.as_bytes().to_vec()))
     }
 
-    pub fn read(file: &mut BufReader<File>) -> crate::Result<Self> {
+    pub fn read<R: Read>(file: &mut R) -> crate::Result<Self> {
         let data = &mut [0; Self::FIXED_HEADER_SIZE];
         file.read_exact(data)?;
         if !data.starts_with(&JAM_SIGNATURE) {
@@ -198,8 +222,13 @@ impl JamMessageHeader {
         convert_u32!(password_crc, data);
         convert_u32!(cost, data);
 
-        let mut subfield_data = vec![0; subfield_data_len as usize];
-        file.read_exact(&mut subfield_data)?;
+        // the length comes from the file, only allocate what the file really contains
+        let mut subfield_data = Vec::new();
+        file.take(subfield_data_len as u64)
+            .read_to_end(&mut subfield_data)?;
+        if subfield_data.len() != subfield_data_len as usize {
+            return Err(JamError::SubfieldDataOutOfRange(subfield_data_len).into());
+        }
 
         let mut sub_fields = Vec::new();
         let mut idx = 0;
@@ -229,18 +258,26 @@ impl JamMessageHeader {
         })
     }
 
-    pub fn write(&self, file: &mut BufWriter<File>) -> crate::Result<()> {
+    /// Length of the subfield data in bytes (SubfieldLen)
+    pub fn subfield_len(&self) -> u32 {
+        self.sub_fields
+            .iter()
+            .map(|sf| 8 + sf.content.len() as u32)
+            .sum()
+    }
+
+    /// Size of the whole header record including subfields
+    pub fn header_size(&self) -> u64 {
+        Self::FIXED_HEADER_SIZE as u64 + self.subfield_len() as u64
+    }
+
+    pub fn write<W: Write>(&self, file: &mut W) -> crate::Result<()> {
         file.write_all(&JAM_SIGNATURE)?;
         // revision
         file.write_all(&u16::to_le_bytes(1))?;
         // reserved_word
         file.write_all(&u16::to_le_bytes(0))?;
-        let subfield_data_len = self
-            .sub_fields
-            .iter()
-            .map(|sf| 8 + sf.content.len())
-            .sum::<usize>();
-        file.write_all(&u32::to_le_bytes(subfield_data_len as u32))?;
+        file.write_all(&self.subfield_len().to_le_bytes())?;
         file.write_all(&self.times_read.to_le_bytes())?;
         file.write_all(&self.msgid_crc.to_le_bytes())?;
         file.write_all(&self.replycrc.to_le_bytes())?;
@@ -462,7 +499,7 @@ impl From<SubfieldType> for u32 {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct MessageSubfield {
     field_type: SubfieldType,
     content: BString,
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use super::{
+    extensions, jdx_record::JamIndexRecord, jhr_header::JHRHeaderInfo,
+    last_read_storage::JamLastReadStorage, msg_header::JamMessageHeader, JamError, JamMessageBase,
+};
+
+/// Outcome of `JamMessageBase::pack`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamPackReport {
+    /// Message numbers that were removed from the message base.
+    pub removed_messages: Vec<u32>,
+    /// Number of bytes the .JHR and .JDT files shrunk.
+    pub reclaimed_bytes: u64,
+    /// The new lowest message number.
+    pub base_msg_num: u32,
+}
+
+const TMP_SUFFIX: &str = "tmp";
+
+impl JamMessageBase {
+    /// Removes all deleted messages and orphaned message text from the message base.
+    ///
+    /// # Remarks
+    /// Messages are not renumbered. Leading deleted messages are removed from the
+    /// index and BaseMsgNum is advanced, deleted messages in between are kept as
+    /// unused (-1) index records. Reply links pointing to removed messages are
+    /// fixed up and last read pointers are moved to the nearest remaining message.
+    pub fn pack(&mut self) -> crate::Result<JamPackReport> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let old_base = self.header_info.base_msg_num;
+        let index = self.read_index()?;
+
+        let mut headers = Vec::with_capacity(index.len());
+        {
+            let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
+            let mut reader = BufReader::new(File::open(header_file_name)?);
+            for record in &index {
+                if record.is_unused() {
+                    headers.push(None);
+                    continue;
+                }
+                reader.seek(SeekFrom::Start(record.header_offset as u64))?;
+                headers.push(Some(JamMessageHeader::read(&mut reader)?));
+            }
+        }
+
+        let number = |i: usize| old_base + i as u32;
+        let mut kept = HashSet::new();
+        let mut removed_messages = Vec::new();
+        let mut reply_next = HashMap::new();
+        for (i, header) in headers.iter().enumerate() {
+            match header {
+                Some(header) if !header.is_deleted() => {
+                    kept.insert(number(i));
+                }
+                Some(_) => removed_messages.push(number(i)),
+                None => {}
+            }
+            if let Some(header) = header {
+                reply_next.insert(number(i), header.replynext);
+            }
+        }
+
+        let first_kept = headers
+            .iter()
+            .position(|h| matches!(h, Some(h) if !h.is_deleted()))
+            .unwrap_or(headers.len());
+        let new_base = number(first_kept);
+
+        // follows the original replynext chain until a message is found that survives the pack.
+        let next_kept = |mut num: u32| {
+            let mut visited = HashSet::new();
+            while num != 0 && !kept.contains(&num) {
+                if !visited.insert(num) {
+                    return 0;
+                }
+                num = reply_next.get(&num).copied().unwrap_or_default();
+            }
+            num
+        };
+
+        let header_file_name = self.file_name.with_extension(extensions::HEADER_DATA);
+        let text_file_name = self.file_name.with_extension(extensions::TEXT_DATA);
+        let index_file_name = self.file_name.with_extension(extensions::MESSAGE_INDEX);
+        let tmp_header =
+            header_file_name.with_extension(format!("{}.{}", extensions::HEADER_DATA, TMP_SUFFIX));
+        let tmp_text =
+            text_file_name.with_extension(format!("{}.{}", extensions::TEXT_DATA, TMP_SUFFIX));
+        let tmp_index =
+            index_file_name.with_extension(format!("{}.{}", extensions::MESSAGE_INDEX, TMP_SUFFIX));
+
+        let old_size =
+            fs::metadata(&header_file_name)?.len() + fs::metadata(&text_file_name)?.len();
+
+        let mut header_block = vec![0; JHRHeaderInfo::JHR_HEADER_SIZE as usize];
+        self.with_header_file(|file| {
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header_block)?;
+            Ok(())
+        })?;
+        let mut header_writer = BufWriter::new(File::create(&tmp_header)?);
+        header_writer.write_all(&header_block)?;
+        let mut header_offset = JHRHeaderInfo::JHR_HEADER_SIZE;
+
+        let mut text_reader = File::open(&text_file_name)?;
+        let text_len = text_reader.metadata()?.len();
+        let mut text_writer = BufWriter::new(File::create(&tmp_text)?);
+        let mut text_offset = 0u64;
+        let mut index_writer = BufWriter::new(File::create(&tmp_index)?);
+
+        for (i, header) in headers.into_iter().enumerate().skip(first_kept) {
+            let mut header = match header {
+                Some(header) if !header.is_deleted() => header,
+                _ => {
+                    JamIndexRecord::UNUSED.write(&mut index_writer)?;
+                    continue;
+                }
+            };
+
+            if header.reply_to != 0 && !kept.contains(&header.reply_to) {
+                header.reply_to = 0;
+            }
+            header.reply1st = next_kept(header.reply1st);
+            header.replynext = next_kept(header.replynext);
+
+            if header.offset as u64 + header.txt_len as u64 > text_len {
+                return Err(JamError::TextOutOfRange(header.offset, header.txt_len).into());
+            }
+            let mut text = vec![0; header.txt_len as usize];
+            text_reader.seek(SeekFrom::Start(header.offset as u64))?;
+            text_reader.read_exact(&mut text)?;
+            text_writer.write_all(&text)?;
+            header.offset = text_offset as u32;
+            text_offset += text.len() as u64;
+
+            header.write(&mut header_writer)?;
+            JamIndexRecord::new(header.get_to_crc(), header_offset as u32)
+                .write(&mut index_writer)?;
+            header_offset += header.header_size();
+            log::info!("Packed message {}", number(i));
+        }
+        header_writer.flush()?;
+        text_writer.flush()?;
+        index_writer.flush()?;
+        drop(header_writer);
+        drop(text_writer);
+        drop(index_writer);
+
+        // The .JHR file is locked, so its content gets replaced through the locked handle.
+        self.with_header_file(|file| {
+            file.seek(SeekFrom::Start(0))?;
+            let len = io::copy(&mut File::open(&tmp_header)?, file)?;
+            file.set_len(len)?;
+            Ok(())
+        })?;
+        fs::remove_file(&tmp_header)?;
+        fs::rename(&tmp_text, &text_file_name)?;
+        fs::rename(&tmp_index, &index_file_name)?;
+
+        self.header_info.base_msg_num = new_base;
+        self.header_info.active_msgs = kept.len() as u32;
+        self.write_jhr_header()?;
+        self.pack_last_read(&kept, new_base)?;
+
+        let new_size = header_offset + text_offset;
+        Ok(JamPackReport {
+            removed_messages,
+            reclaimed_bytes: old_size.saturating_sub(new_size),
+            base_msg_num: new_base,
+        })
+    }
+
+    /// Removes deleted last read records and moves last read pointers
+    /// that point to removed messages to the nearest remaining message.
+    fn pack_last_read(&self, kept: &HashSet<u32>, new_base: u32) -> crate::Result<()> {
+        let mut kept: Vec<u32> = kept.iter().copied().collect();
+        kept.sort_unstable();
+        let nearest = |num: u32| {
+            if num == 0 || kept.binary_search(&num).is_ok() {
+                return num;
+            }
+            match kept.partition_point(|&k| k < num) {
+                0 => new_base.saturating_sub(1),
+                i => kept[i - 1],
+            }
+        };
+
+        let last_read = self
+            .read_last_read_file()?
+            .into_iter()
+            .filter(|lr| !lr.is_deleted())
+            .map(|lr| JamLastReadStorage {
+                last_read_msg: nearest(lr.last_read_msg),
+                high_read_msg: nearest(lr.high_read_msg),
+                ..lr
+            });
+
+        let last_read_file_name = self.file_name.with_extension(extensions::LASTREAD_INFO);
+        let mut writer = BufWriter::new(File::create(last_read_file_name)?);
+        for lr in last_read {
+            lr.write(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use super::{attributes, msg_header::JamMessageHeader, pack::JamPackReport, JamMessageBase};
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Defines which messages `JamMessageBase::purge` deletes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamPurgePolicy {
+    max_age_days: Option<u32>,
+    max_messages: Option<u32>,
+    keep_unreceived_private: bool,
+    pack: bool,
+}
+
+impl JamPurgePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deletes messages older than the given number of days.
+    ///
+    /// The age is taken from DateReceived, messages without receive date use DateWritten.
+    pub fn with_max_age_days(mut self, days: u32) -> Self {
+        self.max_age_days = Some(days);
+        self
+    }
+
+    /// Keeps only the newest `count` messages.
+    pub fn with_max_messages(mut self, count: u32) -> Self {
+        self.max_messages = Some(count);
+        self
+    }
+
+    /// Keeps private messages that weren't read by the addressee.
+    pub fn with_keep_unreceived_private(mut self, keep: bool) -> Self {
+        self.keep_unreceived_private = keep;
+        self
+    }
+
+    /// Packs the message base after purging.
+    pub fn with_pack(mut self, pack: bool) -> Self {
+        self.pack = pack;
+        self
+    }
+
+    fn is_protected(&self, header: &JamMessageHeader) -> bool {
+        header.attributes & attributes::MSG_LOCKED != 0
+            || self.keep_unreceived_private
+                && header.attributes & attributes::MSG_PRIVATE != 0
+                && header.attributes & attributes::MSG_READ == 0
+    }
+}
+
+/// Outcome of `JamMessageBase::purge`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamPurgeReport {
+    /// Messages deleted because they exceeded the maximum age.
+    pub deleted_by_age: Vec<u32>,
+    /// Messages deleted because they exceeded the maximum message count.
+    pub deleted_by_count: Vec<u32>,
+    /// Messages that matched the policy but were kept (locked or unreceived private mail).
+    pub kept_messages: Vec<u32>,
+    /// Result of the pack, if the policy requested one.
+    pub pack: Option<JamPackReport>,
+}
+
+impl JamPurgeReport {
+    pub fn deleted_count(&self) -> usize {
+        self.deleted_by_age.len() + self.deleted_by_count.len()
+    }
+}
+
+impl JamMessageBase {
+    /// Deletes messages according to the purge policy.
+    ///
+    /// # Remarks
+    /// Messages with the MSG_LOCKED attribute are never deleted.
+    pub fn purge(&mut self, policy: &JamPurgePolicy) -> crate::Result<JamPurgeReport> {
+        self.purge_at(policy, super::unix_time_now())
+    }
+
+    pub(crate) fn purge_at(
+        &mut self,
+        policy: &JamPurgePolicy,
+        now: u32,
+    ) -> crate::Result<JamPurgeReport> {
+        let _lock = self.lock()?;
+        self.read_jhr_header()?;
+        let headers: Vec<_> = self
+            .read_indexed_headers()?
+            .into_iter()
+            .filter(|(_, header)| !header.is_deleted())
+            .collect();
+
+        let oldest_allowed = policy
+            .max_age_days
+            .map(|days| now.saturating_sub(days.saturating_mul(SECONDS_PER_DAY)));
+        let count_limit = policy
+            .max_messages
+            .map(|max| headers.len().saturating_sub(max as usize));
+
+        let mut report = JamPurgeReport::default();
+        for (i, (number, header)) in headers.iter().enumerate() {
+            let date = if header.date_received != 0 {
+                header.date_received
+            } else {
+                header.date_written
+            };
+            let too_old = oldest_allowed.is_some_and(|oldest| date < oldest);
+            let too_many = count_limit.is_some_and(|limit| i < limit);
+            if !too_old && !too_many {
+                continue;
+            }
+            if policy.is_protected(header) {
+                report.kept_messages.push(*number);
+                continue;
+            }
+            self.delete_message(*number)?;
+            if too_old {
+                report.deleted_by_age.push(*number);
+            } else {
+                report.deleted_by_count.push(*number);
+            }
+        }
+
+        if policy.pack {
+            report.pack = Some(self.pack()?);
+        }
+        Ok(report)
+    }
+}
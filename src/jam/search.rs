@@ -0,0 +1,224 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+};
+
+use bstr::{BString, ByteSlice};
+use chrono::NaiveDateTime;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    extensions, jdx_record::JamIndexRecord, jhr_header::JHRHeaderInfo,
+    msg_header::JamMessageHeader, JamError, JamMessageBase,
+};
+
+/// Which messages a `JamSearchQuery` matches regarding the MSG_DELETED attribute
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DeletedFilter {
+    #[default]
+    Active,
+    Deleted,
+    All,
+}
+
+/// Query for `JamMessageBase::search`, all criteria need to match.
+///
+/// Names are compared case insensitive, subject and text are case insensitive substring matches.
+#[derive(Debug, Default, Clone)]
+pub struct JamSearchQuery {
+    from: Option<BString>,
+    to: Option<BString>,
+    subject: Option<BString>,
+    text: Option<BString>,
+    written_after: Option<u32>,
+    written_before: Option<u32>,
+    attributes: u32,
+    deleted: DeletedFilter,
+}
+
+impl JamSearchQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_from(mut self, name: BString) -> Self {
+        self.from = Some(name.to_ascii_lowercase().into());
+        self
+    }
+
+    /// Recipient queries use the .JDX recipient crcs and only read matching headers.
+    pub fn with_to(mut self, name: BString) -> Self {
+        self.to = Some(name.to_ascii_lowercase().into());
+        self
+    }
+
+    pub fn with_subject(mut self, subject: BString) -> Self {
+        self.subject = Some(subject.to_ascii_lowercase().into());
+        self
+    }
+
+    pub fn with_text(mut self, text: BString) -> Self {
+        self.text = Some(text.to_ascii_lowercase().into());
+        self
+    }
+
+    /// Matches messages written within `from..=to` (DateWritten)
+    pub fn with_date_range(mut self, from: NaiveDateTime, to: NaiveDateTime) -> Self {
+        self.written_after = Some(from.and_utc().timestamp() as u32);
+        self.written_before = Some(to.and_utc().timestamp() as u32);
+        self
+    }
+
+    /// Matches messages that have all the given attributes set
+    pub fn with_attributes(mut self, attributes: u32) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_deleted(mut self, deleted: DeletedFilter) -> Self {
+        self.deleted = deleted;
+        self
+    }
+
+    fn matches_header(&self, header: &JamMessageHeader) -> bool {
+        let deleted_ok = match self.deleted {
+            DeletedFilter::Active => !header.is_deleted(),
+            DeletedFilter::Deleted => header.is_deleted(),
+            DeletedFilter::All => true,
+        };
+        deleted_ok
+            && header.attributes & self.attributes == self.attributes
+            && self
+                .written_after
+                .iter()
+                .all(|after| header.date_written >= *after)
+            && self
+                .written_before
+                .iter()
+                .all(|before| header.date_written <= *before)
+            && name_matches(header.get_from(), &self.from)
+            && name_matches(header.get_to(), &self.to)
+            && contains(header.get_subject().map(|s| s.as_slice()), &self.subject)
+    }
+}
+
+/// A message found by `JamMessageBase::search_messages`
+#[derive(Debug, Clone)]
+pub struct JamSearchResult {
+    pub message_number: u32,
+    pub header: JamMessageHeader,
+    pub text: BString,
+}
+
+impl JamMessageBase {
+    /// Returns the numbers of all messages matching the query in ascending order.
+    pub fn search(&self, query: &JamSearchQuery) -> crate::Result<Vec<u32>> {
+        Ok(self
+            .scan(query)?
+            .into_iter()
+            .map(|(message_number, _)| message_number)
+            .collect())
+    }
+
+    /// Returns header and text of all messages matching the query in ascending order.
+    pub fn search_messages(&self, query: &JamSearchQuery) -> crate::Result<Vec<JamSearchResult>> {
+        let matches = self.scan(query)?;
+        if matches.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut text_file = File::open(self.file_name.with_extension(extensions::TEXT_DATA))?;
+        matches
+            .into_iter()
+            .map(|(message_number, header)| {
+                let mut text = vec![0; header.txt_len as usize];
+                text_file.seek(SeekFrom::Start(header.offset as u64))?;
+                text_file.read_exact(&mut text)?;
+                Ok(JamSearchResult {
+                    message_number,
+                    header,
+                    text: text.into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns number & header of all messages matching the query.
+    ///
+    /// # Remarks
+    /// The .JHR file is read at once and scanned in parallel, the .JDT file
+    /// is only read if the query searches the message text.
+    fn scan(&self, query: &JamSearchQuery) -> crate::Result<Vec<(u32, JamMessageHeader)>> {
+        let index_data = fs::read(self.file_name.with_extension(extensions::MESSAGE_INDEX))?;
+        if index_data.len() as u64 % JamIndexRecord::RECORD_SIZE != 0 {
+            return Err(JamError::IndexFileCorrupted.into());
+        }
+        let to_crc = query.to.as_ref().map(JamMessageBase::get_crc);
+        let base = self.header_info.base_msg_num;
+        let candidates: Vec<(u32, JamIndexRecord)> = index_data
+            .chunks_exact(JamIndexRecord::RECORD_SIZE as usize)
+            .enumerate()
+            .filter_map(|(i, mut data)| {
+                let record = JamIndexRecord::load(&mut data).ok()?;
+                if record.is_unused() || to_crc.is_some_and(|crc| crc != record.to_crc) {
+                    return None;
+                }
+                Some((base + i as u32, record))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let header_data = fs::read(self.file_name.with_extension(extensions::HEADER_DATA))?;
+        let text_file_name = self.file_name.with_extension(extensions::TEXT_DATA);
+        let text_data = match query.text {
+            Some(_) => Some(fs::read(&text_file_name)?),
+            None => None,
+        };
+        let text_len = match &text_data {
+            Some(text_data) => text_data.len() as u64,
+            None => fs::metadata(&text_file_name)?.len(),
+        };
+        let res = candidates
+            .into_par_iter()
+            .filter_map(|(message_number, record)| {
+                if (record.header_offset as u64) < JHRHeaderInfo::JHR_HEADER_SIZE {
+                    return None;
+                }
+                let mut data = header_data.get(record.header_offset as usize..)?;
+                let header = JamMessageHeader::read(&mut data).ok()?;
+                if !query.matches_header(&header)
+                    || header.offset as u64 + header.txt_len as u64 > text_len
+                {
+                    return None;
+                }
+                if let Some(text_data) = &text_data {
+                    let start = header.offset as usize;
+                    let text = &text_data[start..start + header.txt_len as usize];
+                    if !contains(Some(text), &query.text) {
+                        return None;
+                    }
+                }
+                Some((message_number, header))
+            })
+            .collect();
+        Ok(res)
+    }
+}
+
+fn name_matches(name: Option<&BString>, needle: &Option<BString>) -> bool {
+    match needle {
+        Some(needle) => name.is_some_and(|name| name.eq_ignore_ascii_case(needle)),
+        None => true,
+    }
+}
+
+/// `needle` is already lowercase
+fn contains(haystack: Option<&[u8]>, needle: &Option<BString>) -> bool {
+    match needle {
+        Some(needle) => {
+            haystack.is_some_and(|haystack| haystack.to_ascii_lowercase().contains_str(needle))
+        }
+        None => true,
+    }
+}
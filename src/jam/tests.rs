@@ -1,5 +1,9 @@
 use super::*;
+use chrono::NaiveDate;
 use pretty_assertions::assert_eq;
+use purge::JamPurgePolicy;
+use search::{DeletedFilter, JamSearchQuery};
+use tempfile::TempDir;
 
 #[test]
 fn test_open_base() {
@@ -80,3 +84,555 @@ fn test_iter_elebbs() {
     }
     assert_eq!(4, base.iter().count());
 }
+
+fn copy_base(name: &str, tmpdir: &TempDir) -> PathBuf {
+    let dest = tmpdir.path().join(name);
+    for ext in ["jhr", "jdt", "jdx", "jlr"] {
+        fs::copy(
+            Path::new("data/jam").join(name).with_extension(ext),
+            dest.with_extension(ext),
+        )
+        .unwrap();
+    }
+    dest
+}
+
+#[test]
+fn test_pack() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let mut base = JamMessageBase::open(&path).unwrap();
+    let txt2 = base.read_msg_text(&base.read_header(2).unwrap()).unwrap();
+    let txt4 = base.read_msg_text(&base.read_header(4).unwrap()).unwrap();
+    base.delete_message(1).unwrap();
+    base.delete_message(3).unwrap();
+
+    let report = base.pack().unwrap();
+    assert_eq!(report.removed_messages, vec![1, 3]);
+    assert_eq!(report.base_msg_num, 2);
+    assert!(report.reclaimed_bytes > 0);
+
+    let base = JamMessageBase::open(&path).unwrap();
+    assert_eq!(base.base_messagenumber(), 2);
+    assert_eq!(base.active_messages(), 2);
+    assert_eq!(base.highest_messagenumber().unwrap(), 4);
+    assert!(base.read_header(1).is_err());
+    assert!(base.read_header(3).is_err());
+
+    let header = base.read_header(2).unwrap();
+    assert_eq!(header.get_subject().unwrap(), "Hello All");
+    assert_eq!(header.reply1st, 0);
+    assert_eq!(base.read_msg_text(&header).unwrap(), txt2);
+
+    let header = base.read_header(4).unwrap();
+    assert_eq!(base.read_msg_text(&header).unwrap(), txt4);
+    assert_eq!(base.iter().count(), 2);
+}
+
+#[test]
+fn test_pack_moves_last_read() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let mut base = JamMessageBase::open(&path).unwrap();
+    base.delete_message(2).unwrap();
+    base.pack().unwrap();
+
+    let last_read = base.read_last_read_file().unwrap();
+    assert_eq!(last_read.len(), 1);
+    assert_eq!(last_read[0].last_read_msg, 1);
+    assert_eq!(last_read[0].high_read_msg, 1);
+
+    let header = base.read_header(3).unwrap();
+    assert_eq!(header.reply_to, 0);
+}
+
+#[test]
+// POSIX record locks of other unix systems don't exclude handles of the same process
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+fn test_lock() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let mut base = JamMessageBase::open(&path).unwrap();
+    let other = JamMessageBase::open(&path).unwrap();
+
+    let lock = base.lock().unwrap();
+    assert!(other.try_lock().unwrap().is_none());
+    assert!(other
+        .lock_timeout(std::time::Duration::from_millis(30))
+        .is_err());
+
+    // locks of the same base are reentrant
+    base.delete_message(1).unwrap();
+    assert!(base.try_lock().unwrap().is_some());
+
+    drop(lock);
+    assert!(other.try_lock().unwrap().is_some());
+}
+
+#[test]
+fn test_lock_other_thread() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let base = JamMessageBase::open(&path).unwrap();
+
+    let lock = base.lock().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            // reentrancy is per thread
+            assert!(base.try_lock().unwrap().is_none());
+            assert!(base
+                .lock_timeout(std::time::Duration::from_millis(30))
+                .is_err());
+        })
+        .join()
+        .unwrap();
+    });
+    drop(lock);
+    std::thread::scope(|s| {
+        s.spawn(|| assert!(base.try_lock().unwrap().is_some()))
+            .join()
+            .unwrap();
+    });
+}
+
+#[test]
+// POSIX record locks of other unix systems don't exclude handles of the same process
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+fn test_lock_header_region() {
+    use crate::util::file_lock::lock_region;
+
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let base = JamMessageBase::open(&path).unwrap();
+    let _lock = base.lock().unwrap();
+
+    // only the first byte of the .JHR file is locked
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path.with_extension("jhr"))
+        .unwrap();
+    assert!(!lock_region(&file, 0, 1, false).unwrap());
+    assert!(lock_region(&file, 1, 1, false).unwrap());
+}
+
+#[test]
+fn test_write_message_numbering() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+
+    let msg = JamMessage::new(&EchomailAddress::default())
+        .with_from(BString::from("sysop"))
+        .with_to(BString::from("All"))
+        .with_subject(BString::from("Hello"))
+        .with_text(BString::from("Hello World"));
+    assert_eq!(base.write_message(&msg).unwrap(), 1);
+    assert_eq!(base.write_message(&msg).unwrap(), 2);
+
+    let mut base = JamMessageBase::open(&path).unwrap();
+    assert_eq!(base.active_messages(), 2);
+    assert_eq!(base.mod_counter(), 2);
+    assert_eq!(base.highest_messagenumber().unwrap(), 2);
+
+    let header = base.read_header(2).unwrap();
+    assert_eq!(header.message_number, 2);
+    assert_ne!(header.date_received, 0);
+    assert_ne!(header.date_processed, 0);
+    assert_eq!(base.read_msg_text(&header).unwrap(), "Hello World");
+    assert_eq!(
+        base.search_message_index(JamMessageBase::get_crc(&BString::from("all")))
+            .unwrap(),
+        vec![1, 2]
+    );
+
+    base.delete_message(1).unwrap();
+    assert_eq!(JamMessageBase::open(&path).unwrap().active_messages(), 1);
+    base.restore_message(1).unwrap();
+    assert_eq!(JamMessageBase::open(&path).unwrap().active_messages(), 2);
+}
+
+#[test]
+fn test_update_message() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+    let aka = EchomailAddress::default();
+
+    let msg = JamMessage::new(&aka)
+        .with_to(BString::from("All"))
+        .with_subject(BString::from("Hello"))
+        .with_text(BString::from("Hello World"));
+    base.write_message(&msg).unwrap();
+    base.write_message(&msg.clone().with_reply_to(1)).unwrap();
+    let header_len = fs::metadata(path.with_extension("jhr")).unwrap().len();
+
+    // same subfield size -> rewritten in place
+    let mut msg = base.read_message(1).unwrap();
+    msg.set_subject(BString::from("Hallo"));
+    base.update_message(1, &msg).unwrap();
+    assert_eq!(
+        fs::metadata(path.with_extension("jhr")).unwrap().len(),
+        header_len
+    );
+    let header = base.read_header(1).unwrap();
+    assert_eq!(header.get_subject().unwrap(), "Hallo");
+    assert_eq!(header.reply1st, 2);
+
+    // longer subject & new recipient -> appended, index gets repointed
+    let mut msg = base
+        .read_message(1)
+        .unwrap()
+        .with_text(BString::from("Changed text"));
+    msg.set_to(BString::from("Sysop"));
+    msg.set_subject(BString::from("Hello again"));
+    base.update_message(1, &msg).unwrap();
+    assert!(fs::metadata(path.with_extension("jhr")).unwrap().len() > header_len);
+    let msg = base.read_message(1).unwrap();
+    assert_eq!(msg.get_header().get_subject().unwrap(), "Hello again");
+    assert_eq!(msg.get_text(), "Changed text");
+    assert_eq!(msg.get_reply1st(), 2);
+    assert_eq!(
+        base.search_message_index(JamMessageBase::get_crc(&BString::from("sysop")))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(base.active_messages(), 2);
+    assert!(base.check().unwrap().is_ok());
+
+    base.pack().unwrap();
+    assert_eq!(base.read_message(1).unwrap().get_text(), "Changed text");
+    assert!(base.check().unwrap().is_ok());
+
+    let locked = base
+        .read_message(2)
+        .unwrap()
+        .with_attributes(attributes::MSG_LOCKED);
+    base.update_message(2, &locked).unwrap();
+    assert!(base.update_message(2, &msg).is_err());
+}
+
+#[test]
+fn test_search() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+    let aka = EchomailAddress::default();
+    let date = |day| {
+        NaiveDate::from_ymd_opt(2024, 4, day)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    };
+    let msg = |from: &str, to: &str, subject: &str, text: &str, day| {
+        JamMessage::new(&aka)
+            .with_from(BString::from(from))
+            .with_to(BString::from(to))
+            .with_subject(BString::from(subject))
+            .with_text(BString::from(text))
+            .with_date_time(date(day))
+    };
+    base.write_message(&msg("Sysop", "All", "Welcome", "Hello World", 1))
+        .unwrap();
+    base.write_message(&msg("omnibrain", "Sysop", "Re: Welcome", "thanks", 2))
+        .unwrap();
+    base.write_message(
+        &msg("Sysop", "omnibrain", "Rules", "be nice", 3).with_attributes(attributes::MSG_PRIVATE),
+    )
+    .unwrap();
+    base.write_message(&msg("omnibrain", "All", "Bye", "hello again", 4))
+        .unwrap();
+    base.delete_message(4).unwrap();
+
+    let search = |query: JamSearchQuery| base.search(&query).unwrap();
+    assert_eq!(search(JamSearchQuery::new()), vec![1, 2, 3]);
+    assert_eq!(
+        search(JamSearchQuery::new().with_from(BString::from("SYSOP"))),
+        vec![1, 3]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_to(BString::from("all"))),
+        vec![1]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_subject(BString::from("welcome"))),
+        vec![1, 2]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_text(BString::from("HELLO"))),
+        vec![1]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_date_range(date(2), date(3))),
+        vec![2, 3]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_attributes(attributes::MSG_PRIVATE)),
+        vec![3]
+    );
+    assert_eq!(
+        search(JamSearchQuery::new().with_deleted(DeletedFilter::Deleted)),
+        vec![4]
+    );
+    assert_eq!(
+        search(
+            JamSearchQuery::new()
+                .with_text(BString::from("hello"))
+                .with_deleted(DeletedFilter::All)
+        ),
+        vec![1, 4]
+    );
+
+    let res = base
+        .search_messages(&JamSearchQuery::new().with_to(BString::from("omnibrain")))
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].message_number, 3);
+    assert_eq!(res[0].header.get_subject().unwrap(), "Rules");
+    assert_eq!(res[0].text, "be nice");
+}
+
+#[test]
+fn test_purge() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+    let aka = EchomailAddress::default();
+    for i in 0..6 {
+        let msg = match i {
+            0 => JamMessage::new(&aka).with_attributes(attributes::MSG_LOCKED),
+            1 => JamMessage::new(&aka).with_attributes(attributes::MSG_PRIVATE),
+            _ => JamMessage::new(&aka),
+        };
+        base.write_message(&msg).unwrap();
+    }
+    let now = base.read_header(1).unwrap().date_received;
+
+    // nothing is old enough
+    let policy = JamPurgePolicy::new().with_max_age_days(10);
+    let report = base.purge_at(&policy, now + 24 * 60 * 60).unwrap();
+    assert_eq!(report.deleted_count(), 0);
+
+    let policy = JamPurgePolicy::new()
+        .with_max_messages(2)
+        .with_keep_unreceived_private(true);
+    let report = base.purge_at(&policy, now).unwrap();
+    assert_eq!(report.deleted_by_count, vec![3, 4]);
+    assert_eq!(report.kept_messages, vec![1, 2]);
+    assert_eq!(base.active_messages(), 4);
+
+    let policy = JamPurgePolicy::new().with_max_age_days(10).with_pack(true);
+    let report = base.purge_at(&policy, now + 11 * 24 * 60 * 60).unwrap();
+    assert_eq!(report.deleted_by_age, vec![2, 5, 6]);
+    assert_eq!(report.kept_messages, vec![1]);
+    assert_eq!(report.pack.unwrap().removed_messages, vec![2, 3, 4, 5, 6]);
+    assert_eq!(base.active_messages(), 1);
+    assert!(base.check().unwrap().is_ok());
+}
+
+#[test]
+fn test_reply_chain() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+    let aka = EchomailAddress::new(1, 2, 3, 0);
+
+    let parent = JamMessage::new(&aka).with_subject(BString::from("Hello"));
+    assert_eq!(base.write_message(&parent).unwrap(), 1);
+    for _ in 0..3 {
+        let reply = JamMessage::new(&aka)
+            .with_subject(BString::from("Re: Hello"))
+            .with_reply_to(1);
+        base.write_message(&reply).unwrap();
+    }
+    let reply = JamMessage::new(&aka).with_reply_to(3);
+    assert_eq!(base.write_message(&reply).unwrap(), 5);
+
+    let parent = base.read_header(1).unwrap();
+    assert_eq!(parent.reply1st, 2);
+    assert_eq!(base.read_header(2).unwrap().replynext, 3);
+    assert_eq!(base.read_header(4).unwrap().replynext, 0);
+
+    let reply = base.read_header(3).unwrap();
+    assert_eq!(reply.replynext, 4);
+    assert_eq!(reply.reply1st, 5);
+    assert_eq!(reply.replycrc, parent.msgid_crc);
+    assert_eq!(reply.get_reply_id(), parent.get_msgid());
+}
+
+#[test]
+fn test_threads() {
+    let base = JamMessageBase::open("data/jam/general").unwrap();
+    let threads = base.threads().unwrap();
+    assert_eq!(threads.threads.len(), 3);
+    assert_eq!(threads.threads[1].message_number, 2);
+    assert_eq!(threads.threads[1].replies, vec![threads::JamThread::new(3)]);
+    assert!(threads.orphans.is_empty());
+    assert!(threads.cycles.is_empty());
+}
+
+#[test]
+fn test_deep_thread() {
+    let mut thread = threads::JamThread::new(1_000_000);
+    for num in (1..1_000_000).rev() {
+        let mut parent = threads::JamThread::new(num);
+        parent.replies.push(thread);
+        thread = parent;
+    }
+    assert_eq!(thread.message_count(), 1_000_000);
+    let copy = thread.clone();
+    assert!(copy == thread);
+    thread.replies[0].message_number = 0;
+    assert!(copy != thread);
+}
+
+#[test]
+fn test_threads_broken_links() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("jambase");
+    let mut base = JamMessageBase::create(&path).unwrap();
+    let aka = EchomailAddress::default();
+
+    let parent = JamMessage::new(&aka);
+    let parent_crc = parent.get_msgid_crc();
+    base.write_message(&parent).unwrap();
+    // reply found by the REPLY crc
+    let mut reply = JamMessage::new(&aka);
+    reply.set_reply_crc(parent_crc);
+    base.write_message(&reply).unwrap();
+    // orphan
+    base.write_message(&JamMessage::new(&aka).with_reply_to(99))
+        .unwrap();
+    // cycle 4 <-> 5
+    base.write_message(&JamMessage::new(&aka)).unwrap();
+    base.write_message(&JamMessage::new(&aka).with_reply_to(4))
+        .unwrap();
+    let (_, index) = base.read_index_record(4).unwrap();
+    let mut header = base.read_header(4).unwrap();
+    header.reply_to = 5;
+    base.write_header_at(index.header_offset, &header).unwrap();
+
+    let threads = base.threads().unwrap();
+    let roots: Vec<u32> = threads.threads.iter().map(|t| t.message_number).collect();
+    assert_eq!(roots, vec![1, 3, 4]);
+    assert_eq!(threads.threads[0].message_count(), 2);
+    assert_eq!(threads.threads[2].replies[0].message_number, 5);
+    assert_eq!(threads.orphans, vec![3]);
+    assert_eq!(threads.cycles, vec![4]);
+}
+
+#[test]
+fn test_check() {
+    let base = JamMessageBase::open("data/jam/general").unwrap();
+    let report = base.check().unwrap();
+    assert_eq!(report.checked_messages, 4);
+    assert!(report.is_ok(), "{:?}", report.issues);
+}
+
+#[test]
+fn test_repair() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    // corrupt the index & the active message counter
+    let mut index = fs::read(path.with_extension("jdx")).unwrap();
+    index[12..16].copy_from_slice(&7u32.to_le_bytes());
+    index.truncate(28);
+    fs::write(path.with_extension("jdx"), index).unwrap();
+    let mut header = fs::read(path.with_extension("jhr")).unwrap();
+    header[12..16].copy_from_slice(&9u32.to_le_bytes());
+    header.extend(b"garbage");
+    fs::write(path.with_extension("jhr"), header).unwrap();
+
+    let mut base = JamMessageBase::open(&path).unwrap();
+    let report = base.check().unwrap();
+    assert_eq!(
+        report.issues,
+        vec![
+            check::JamIssue::IndexFileTruncated(28),
+            check::JamIssue::InvalidHeader {
+                message_number: 2,
+                header_offset: 7
+            },
+            check::JamIssue::ActiveMessagesMismatch {
+                header: 9,
+                actual: 2
+            },
+        ]
+    );
+
+    let report = base.repair().unwrap();
+    assert_eq!(report.messages, 4);
+    assert_eq!(report.active_messages, 4);
+    assert_eq!(report.unreadable_bytes, 7);
+    assert!(base.check().unwrap().is_ok());
+    assert_eq!(base.read_header(4).unwrap().get_subject().unwrap(), "test");
+}
+
+#[test]
+fn test_repair_huge_lengths() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let mut base = JamMessageBase::open(&path).unwrap();
+    let (_, first) = base.read_index_record(1).unwrap();
+    let (_, second) = base.read_index_record(2).unwrap();
+
+    // SubfieldLen of the first header & TxtLen of the second header
+    let mut header = fs::read(path.with_extension("jhr")).unwrap();
+    let pos = first.header_offset as usize + 8;
+    header[pos..pos + 4].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    fs::write(path.with_extension("jhr"), header).unwrap();
+    let mut second_header = base.read_header(2).unwrap();
+    second_header.txt_len = 0xFFFF_FFF0;
+    base.write_header_at(second.header_offset, &second_header)
+        .unwrap();
+
+    assert!(base.read_header(1).is_err());
+    assert!(base.read_msg_text(&second_header).is_err());
+    let report = base.check().unwrap();
+    assert!(report.issues.contains(&check::JamIssue::InvalidHeader {
+        message_number: 1,
+        header_offset: first.header_offset
+    }));
+
+    let report = base.repair().unwrap();
+    assert_eq!(report.messages, 3);
+}
+
+#[test]
+fn test_last_read() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_base("general", &tmpdir);
+    let mut base = JamMessageBase::open(&path).unwrap();
+    let user_crc = base.read_last_read_file().unwrap()[0].user_crc;
+    assert_eq!(base.unread_count(user_crc, 1).unwrap(), 2);
+
+    base.find_last_read(user_crc, 1).unwrap().unwrap();
+    base.set_last_read(user_crc, 1, 3, 3).unwrap();
+    assert_eq!(base.unread_count(user_crc, 1).unwrap(), 1);
+
+    base.set_last_read(5, 7, 0, 0).unwrap();
+    assert_eq!(base.read_last_read_file().unwrap().len(), 2);
+    assert_eq!(base.unread_count(5, 7).unwrap(), 4);
+
+    assert!(base.remove_last_read(5, 7).unwrap());
+    assert!(!base.remove_last_read(5, 7).unwrap());
+    base.set_last_read(9, 9, 4, 4).unwrap();
+    let last_read = base.read_last_read_file().unwrap();
+    assert_eq!(last_read.len(), 2);
+    assert_eq!(last_read[1].user_crc, 9);
+    assert_eq!(last_read[0].last_read_msg, 3);
+
+    assert_eq!(base.retain_last_read(|lr| lr.user_id != 1).unwrap(), 1);
+    let last_read = base.read_last_read_file().unwrap();
+    assert_eq!(last_read.len(), 1);
+    assert_eq!(last_read[0].user_id, 9);
+
+    // another instance packs away the first messages and moves BaseMsgNum
+    base.set_last_read(9, 9, 3, 3).unwrap();
+    let mut other = JamMessageBase::open(&path).unwrap();
+    other.delete_message(1).unwrap();
+    other.delete_message(2).unwrap();
+    other.pack().unwrap();
+    assert_eq!(base.unread_count(9, 9).unwrap(), 1);
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+
+use bstr::BString;
+
+use crate::util::crc32::CRC_SEED;
+
+use super::JamMessageBase;
+
+/// A message and all replies to it.
+///
+/// # Remarks
+/// Reply chains can be very long, so counting, comparing, cloning and dropping
+/// threads doesn't use recursion.
+#[derive(Debug, Eq)]
+pub struct JamThread {
+    pub message_number: u32,
+    pub replies: Vec<JamThread>,
+}
+
+impl JamThread {
+    pub fn new(message_number: u32) -> Self {
+        Self {
+            message_number,
+            replies: Vec::new(),
+        }
+    }
+
+    /// Number of messages in this thread (including this one)
+    pub fn message_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(thread) = stack.pop() {
+            count += 1;
+            stack.extend(&thread.replies);
+        }
+        count
+    }
+}
+
+impl PartialEq for JamThread {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if a.message_number != b.message_number || a.replies.len() != b.replies.len() {
+                return false;
+            }
+            stack.extend(a.replies.iter().zip(&b.replies));
+        }
+        true
+    }
+}
+
+impl Clone for JamThread {
+    fn clone(&self) -> Self {
+        build_tree(
+            self,
+            |thread| thread.message_number,
+            |thread| thread.replies.iter(),
+        )
+    }
+}
+
+impl Drop for JamThread {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.replies);
+        while let Some(mut thread) = stack.pop() {
+            stack.append(&mut thread.replies);
+        }
+    }
+}
+
+/// All threads of a message base.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JamThreads {
+    /// Thread roots in message number order
+    pub threads: Vec<JamThread>,
+
+    /// Replies to messages that couldn't be found.
+    /// These messages are thread roots as well.
+    pub orphans: Vec<u32>,
+
+    /// Messages where the reply links formed a cycle.
+    /// The cycle is broken at the lowest message number, which becomes a thread root.
+    pub cycles: Vec<u32>,
+}
+
+impl JamMessageBase {
+    /// Builds the reply threads of all active messages.
+    ///
+    /// # Remarks
+    /// The numeric reply links (ReplyTo, Reply1st and ReplyNext) are used first.
+    /// If these are broken, the REPLY subfield is matched against the MSGID subfields
+    /// and as last resort the REPLY crc against the MSGID crcs.
+    pub fn threads(&self) -> crate::Result<JamThreads> {
+        let headers: Vec<_> = self
+            .read_indexed_headers()?
+            .into_iter()
+            .filter(|(_, header)| !header.is_deleted())
+            .collect();
+        let numbers: HashSet<u32> = headers.iter().map(|(num, _)| *num).collect();
+
+        let mut by_msgid: HashMap<&BString, u32> = HashMap::new();
+        let mut by_msgid_crc = HashMap::new();
+        for (num, header) in &headers {
+            if let Some(msgid) = header.get_msgid() {
+                by_msgid.entry(msgid).or_insert(*num);
+            }
+            if has_crc(header.msgid_crc) {
+                by_msgid_crc.entry(header.msgid_crc).or_insert(*num);
+            }
+        }
+
+        // parents derived from the Reply1st -> ReplyNext chains
+        let reply_next: HashMap<u32, u32> = headers
+            .iter()
+            .map(|(num, header)| (*num, header.replynext))
+            .collect();
+        let mut chain_parent = HashMap::new();
+        for (num, header) in &headers {
+            let mut next = header.reply1st;
+            while next != 0 && numbers.contains(&next) && !chain_parent.contains_key(&next) {
+                chain_parent.insert(next, *num);
+                next = reply_next[&next];
+            }
+        }
+
+        let mut parents = HashMap::new();
+        let mut orphans = Vec::new();
+        for (num, header) in &headers {
+            let parent = Some(header.reply_to)
+                .filter(|p| numbers.contains(p))
+                .or_else(|| chain_parent.get(num).copied())
+                .or_else(|| {
+                    header
+                        .get_reply_id()
+                        .and_then(|id| by_msgid.get(id).copied())
+                })
+                .or_else(|| {
+                    Some(header.replycrc)
+                        .filter(|crc| has_crc(*crc))
+                        .and_then(|crc| by_msgid_crc.get(&crc).copied())
+                })
+                .filter(|p| p != num);
+
+            match parent {
+                Some(parent) => {
+                    parents.insert(*num, parent);
+                }
+                None => {
+                    if header.reply_to != 0
+                        || header.get_reply_id().is_some()
+                        || has_crc(header.replycrc)
+                    {
+                        orphans.push(*num);
+                    }
+                }
+            }
+        }
+
+        let cycles = break_cycles(&mut parents);
+
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (num, _) in &headers {
+            match parents.get(num) {
+                Some(parent) => children.entry(*parent).or_default().push(*num),
+                None => roots.push(*num),
+            }
+        }
+        for replies in children.values_mut() {
+            replies.sort_unstable();
+        }
+        roots.sort_unstable();
+
+        Ok(JamThreads {
+            threads: roots
+                .into_iter()
+                .map(|root| build_thread(root, &children))
+                .collect(),
+            orphans,
+            cycles,
+        })
+    }
+}
+
+fn has_crc(crc: u32) -> bool {
+    crc != CRC_SEED && crc != 0
+}
+
+/// Removes the parent link of the lowest message number of each cycle.
+fn break_cycles(parents: &mut HashMap<u32, u32>) -> Vec<u32> {
+    let mut cycles = Vec::new();
+    let mut checked = HashSet::new();
+    let mut start_numbers: Vec<u32> = parents.keys().copied().collect();
+    start_numbers.sort_unstable();
+
+    for start in start_numbers {
+        let mut path = Vec::new();
+        let mut on_path = HashSet::new();
+        let mut cur = start;
+        loop {
+            if checked.contains(&cur) {
+                break;
+            }
+            if !on_path.insert(cur) {
+                let pos = path.iter().position(|n| *n == cur).unwrap();
+                let lowest = *path[pos..].iter().min().unwrap();
+                parents.remove(&lowest);
+                cycles.push(lowest);
+                break;
+            }
+            path.push(cur);
+            match parents.get(&cur) {
+                Some(parent) => cur = *parent,
+                None => break,
+            }
+        }
+        checked.extend(path);
+    }
+    cycles.sort_unstable();
+    cycles
+}
+
+fn build_thread(root: u32, children: &HashMap<u32, Vec<u32>>) -> JamThread {
+    let no_replies = Vec::new();
+    build_tree(
+        root,
+        |num| num,
+        |num| children.get(&num).unwrap_or(&no_replies).iter().copied(),
+    )
+}
+
+/// Builds a thread from `root`, `replies_of` returns the direct replies of a node.
+fn build_tree<N: Copy, I: Iterator<Item = N>>(
+    root: N,
+    number_of: impl Fn(N) -> u32,
+    replies_of: impl Fn(N) -> I,
+) -> JamThread {
+    // iterative to not overflow the stack on very long reply chains
+    let mut stack = vec![(JamThread::new(number_of(root)), replies_of(root))];
+    loop {
+        let next = stack.last_mut().unwrap().1.next();
+        match next {
+            Some(reply) => stack.push((JamThread::new(number_of(reply)), replies_of(reply))),
+            None => {
+                let (thread, _) = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some((parent, _)) => parent.replies.push(thread),
+                    None => return thread,
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
+// `is_multiple_of` needs Rust 1.87, remainder checks keep older compilers working
+#![allow(clippy::manual_is_multiple_of)]
+
 #[macro_use]
 pub(crate) mod macros;
 
 pub mod conversion;
 pub mod jam;
+pub mod message_base;
 pub mod pcboard;
 pub mod qwk;
 pub mod util;
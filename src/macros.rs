@@ -43,12 +43,6 @@ macro_rules! convert_u8 {
     };
 }
 
-macro_rules! convert_single_u32 {
-    ( $t:ident, $x:expr ) => {
-        let $t = $x[0] as u32 | ($x[1] as u32) << 8 | ($x[2] as u32) << 16 | ($x[3] as u32) << 24;
-    };
-}
-
 macro_rules! convert_single_u16 {
     ( $t:ident, $x:expr ) => {
         let $t = $x[0] as u16 | ($x[1] as u16) << 8;
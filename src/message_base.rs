@@ -0,0 +1,235 @@
+//! Format neutral access to JAM, PCBoard and QWK message bases.
+
+use std::path::{Path, PathBuf};
+
+use bstr::BString;
+use chrono::NaiveDateTime;
+use thiserror::Error;
+
+use crate::{
+    jam::JamMessageBase, pcboard::PCBoardMessageBase, qwk::QwkMessageBase,
+    util::echmoail::EchomailAddress,
+};
+
+#[derive(Error, Debug)]
+pub enum MessageBaseError {
+    #[error("Can't detect message base format of {0}")]
+    UnknownFormat(PathBuf),
+
+    #[error("Message {0} not found")]
+    MessageNotFound(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageBaseFormat {
+    Jam,
+    PCBoard,
+    Qwk,
+}
+
+impl MessageBaseFormat {
+    /// Detects the format of a message base path as taken by the `open` functions.
+    ///
+    /// # Remarks
+    /// A directory containing control.dat or a .QWK archive is a QWK packet, a path with a .JHR
+    /// file a JAM base and a file starting with a PCBoard header block a PCBoard message file.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let is_qwk = path.join("control.dat").exists() || path.join("CONTROL.DAT").exists();
+            return is_qwk.then_some(MessageBaseFormat::Qwk);
+        }
+        if path.with_extension("jhr").exists() {
+            return Some(MessageBaseFormat::Jam);
+        }
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("qwk"))
+        {
+            return Some(MessageBaseFormat::Qwk);
+        }
+        PCBoardMessageBase::is_message_base(path).then_some(MessageBaseFormat::PCBoard)
+    }
+}
+
+/// Format neutral message flags
+pub mod flags {
+    /// Only visible to sender & recipient
+    pub const PRIVATE: u32 = 0x0001;
+    /// Read by the recipient
+    pub const READ: u32 = 0x0002;
+    /// Deleted, but still stored in the message base
+    pub const DELETED: u32 = 0x0004;
+    /// Created locally
+    pub const LOCAL: u32 = 0x0008;
+    /// Sent to remote
+    pub const SENT: u32 = 0x0010;
+    /// File(s) attached to the message
+    pub const FILE_ATTACH: u32 = 0x0020;
+    /// Return receipt requested
+    pub const RECEIPT_REQUEST: u32 = 0x0040;
+    /// Must not be deleted or changed
+    pub const LOCKED: u32 = 0x0080;
+}
+
+/// A message of any supported message base format.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub number: u32,
+    /// Conference of formats with several conferences per packet (QWK), 0 otherwise.
+    /// Message numbers are only unique within a conference.
+    pub conference: u16,
+    pub from: BString,
+    pub to: BString,
+    pub subject: BString,
+    /// Network address of the sender, if the format stores one
+    pub origin: Option<EchomailAddress>,
+    pub date_written: NaiveDateTime,
+    /// Only available in formats that store a receive date
+    pub date_received: Option<NaiveDateTime>,
+    /// See `flags`
+    pub flags: u32,
+    /// Number of the message this is a reply to, 0 if none
+    pub reply_to: u32,
+    pub password: BString,
+    /// File names of attached files
+    pub attachments: Vec<BString>,
+    /// FTS kludge lines without the leading ^A (e.g. "MSGID: 2:2/3 12345678")
+    pub kludges: Vec<BString>,
+    /// Message text, lines are separated by '\n'
+    pub text: BString,
+}
+
+impl Message {
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.has_flag(flags::DELETED)
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.has_flag(flags::PRIVATE)
+    }
+}
+
+/// Read access shared by all message base formats.
+pub trait MessageBase {
+    fn format(&self) -> MessageBaseFormat;
+
+    /// Number of active (not deleted) messages
+    fn active_messages(&self) -> u32;
+
+    /// Reads a single message, deleted messages are returned with the `DELETED` flag.
+    fn get_message(&self, number: u32) -> crate::Result<Message>;
+
+    /// All messages in storage order including deleted ones.
+    fn messages(&self) -> Box<dyn Iterator<Item = crate::Result<Message>> + '_>;
+}
+
+/// Write access for message base formats that can be converted to.
+pub trait MessageBaseWriter {
+    /// Appends a message and returns the assigned message number.
+    fn write_message(&mut self, msg: &Message) -> crate::Result<u32>;
+
+    /// The number the next written message gets.
+    fn next_message_number(&self) -> crate::Result<u32>;
+
+    /// Skips message numbers so the next written message gets `number`.
+    ///
+    /// # Remarks
+    /// Fails if `number` is lower than `next_message_number`.
+    fn set_next_message_number(&mut self, number: u32) -> crate::Result<()>;
+}
+
+/// Opens a message base of any supported format, see `MessageBaseFormat::detect`.
+pub fn open_message_base<P: AsRef<Path>>(path: P) -> crate::Result<Box<dyn MessageBase>> {
+    let path = path.as_ref();
+    match MessageBaseFormat::detect(path) {
+        Some(MessageBaseFormat::Jam) => Ok(Box::new(JamMessageBase::open(path)?)),
+        Some(MessageBaseFormat::PCBoard) => Ok(Box::new(PCBoardMessageBase::open(path)?)),
+        Some(MessageBaseFormat::Qwk) => Ok(Box::new(QwkMessageBase::open(path, true)?)),
+        None => Err(MessageBaseError::UnknownFormat(path.to_path_buf()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            MessageBaseFormat::detect("data/jam/general"),
+            Some(MessageBaseFormat::Jam)
+        );
+        assert_eq!(
+            MessageBaseFormat::detect("data/pcboard/test"),
+            Some(MessageBaseFormat::PCBoard)
+        );
+        assert_eq!(
+            MessageBaseFormat::detect("data/qwk"),
+            Some(MessageBaseFormat::Qwk)
+        );
+        assert_eq!(MessageBaseFormat::detect("data/missing"), None);
+        assert_eq!(MessageBaseFormat::detect("data/pcboard/test.idx"), None);
+        assert_eq!(MessageBaseFormat::detect("README.md"), None);
+        assert_eq!(MessageBaseFormat::detect("Cargo.toml"), None);
+        assert!(open_message_base("data/missing").is_err());
+    }
+
+    #[test]
+    fn test_jam_messages() {
+        let base = open_message_base("data/jam/general").unwrap();
+        assert_eq!(base.format(), MessageBaseFormat::Jam);
+        let messages: Vec<Message> = base.messages().map(|m| m.unwrap()).collect();
+        assert_eq!(messages.len() as u32, base.active_messages());
+
+        let msg = base.get_message(3).unwrap();
+        assert_eq!(msg, messages[2]);
+        assert_eq!(msg.from, "omnibrain");
+        assert_eq!(msg.subject, "Re: Hello All");
+        assert_eq!(msg.reply_to, 2);
+        assert!(msg.kludges.iter().any(|k| k.starts_with(b"MSGID: ")));
+
+        let msg = base.get_message(4).unwrap();
+        assert_eq!(
+            msg.text,
+            "private message\n\n... Multitasking: Reading in the bathroom\n"
+        );
+    }
+
+    #[test]
+    fn test_pcboard_messages() {
+        let base = open_message_base("data/pcboard/test").unwrap();
+        assert_eq!(base.format(), MessageBaseFormat::PCBoard);
+        assert_eq!(base.messages().count(), 4);
+
+        let msg = base.get_message(3).unwrap();
+        assert_eq!(msg.number, 3);
+        assert_eq!(msg.from, "SYSOP");
+        assert_eq!(msg.to, "ALL");
+        assert_eq!(msg.subject, "Another message");
+        assert_eq!(msg.password, "GROUPPW");
+    }
+
+    #[test]
+    fn test_qwk_messages() {
+        let base = open_message_base("data/qwk").unwrap();
+        assert_eq!(base.format(), MessageBaseFormat::Qwk);
+        let messages: Vec<Message> = base.messages().map(|m| m.unwrap()).collect();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(base.active_messages(), 1);
+
+        let msg = base.get_message(5).unwrap();
+        assert_eq!(msg, messages[0]);
+        assert_eq!(msg.from, "SYSOP");
+        assert_eq!(msg.to, "ALL");
+        assert_eq!(msg.subject, "test");
+        assert!(msg.text.starts_with(b"dwedfwefwe\n"));
+        assert!(base.get_message(1).is_err());
+    }
+}
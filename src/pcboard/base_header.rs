@@ -3,7 +3,7 @@ use std::{
     io::{BufWriter, Read, Write},
 };
 
-use crate::util::basic_real::basicreal_to_u32;
+use crate::util::basic_real::{basicreal_to_u32, is_unsigned_basicreal, u32_to_basicreal};
 
 pub struct PCBoardMessageBaseHeader {
     /// Highest message number in index file
@@ -18,6 +18,10 @@ pub struct PCBoardMessageBaseHeader {
     pub callers: u32,
 
     pub lock_status: [u8; 6],
+
+    /// Callers counter as stored in the file, the BASIC real conversion is lossy
+    /// so it's written back unchanged unless `callers` was changed.
+    raw_callers: u32,
 }
 
 pub const UNLOCKED: [u8; 6] = *b"      ";
@@ -25,6 +29,44 @@ pub const LOCKED: [u8; 6] = *b"LOCKED";
 
 impl PCBoardMessageBaseHeader {
     pub const HEADER_SIZE: usize = 4 * 4 + 6;
+    /// The header is stored in the first 128 byte block of the message file
+    pub const BLOCK_SIZE: usize = 128;
+
+    /// Header of an empty, unlocked message base
+    pub fn new() -> Self {
+        Self {
+            high_msg_num: 0,
+            low_msg_num: 0,
+            active_msgs: 0,
+            callers: 0,
+            lock_status: UNLOCKED,
+            raw_callers: 0,
+        }
+    }
+
+    /// Writes the header block of a new message base.
+    pub(crate) fn create(&self, file: &mut BufWriter<File>) -> crate::Result<()> {
+        self.write_header_to(file)?;
+        file.write_all(&[b' '; Self::BLOCK_SIZE - Self::HEADER_SIZE])?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Checks if the data starts with a message base header: the message counters are
+    /// whole BASIC reals and the lock status is "LOCKED" or spaces.
+    ///
+    /// # Remarks
+    /// The callers counter isn't checked, PCBoard stores values like -2^31 there.
+    pub(crate) fn is_valid(data: &[u8]) -> bool {
+        if data.len() < Self::BLOCK_SIZE {
+            return false;
+        }
+        let counters_valid = data[..12]
+            .chunks_exact(4)
+            .all(|n| is_unsigned_basicreal(u32::from_le_bytes([n[0], n[1], n[2], n[3]])));
+        let lock_status = &data[16..Self::HEADER_SIZE];
+        counters_valid && (lock_status == LOCKED || lock_status == UNLOCKED)
+    }
 
     pub fn load(file: &mut File) -> crate::Result<Self> {
         let data = &mut [0; Self::HEADER_SIZE];
@@ -37,8 +79,8 @@ impl PCBoardMessageBaseHeader {
         let low_msg_num = basicreal_to_u32(low_msg_num);
         convert_u32!(num_active_msgs, data);
         let num_active_msgs = basicreal_to_u32(num_active_msgs);
-        convert_u32!(num_callers, data);
-        let num_callers = basicreal_to_u32(num_callers);
+        convert_u32!(raw_callers, data);
+        let num_callers = basicreal_to_u32(raw_callers);
         let lock_status = [data[0], data[1], data[2], data[3], data[4], data[5]];
 
         Ok(Self {
@@ -47,15 +89,21 @@ impl PCBoardMessageBaseHeader {
             active_msgs: num_active_msgs,
             callers: num_callers,
             lock_status,
+            raw_callers,
         })
     }
 
-    pub(crate) fn write_header_to(&self, file: &mut BufWriter<File>) -> crate::Result<()> {
+    pub(crate) fn write_header_to<W: Write>(&self, file: &mut W) -> crate::Result<()> {
         let mut data = Vec::with_capacity(Self::HEADER_SIZE);
-        data.extend(&self.high_msg_num.to_le_bytes());
-        data.extend(&self.low_msg_num.to_le_bytes());
-        data.extend(&self.active_msgs.to_le_bytes());
-        data.extend(&self.callers.to_le_bytes());
+        data.extend(&u32_to_basicreal(self.high_msg_num).to_le_bytes());
+        data.extend(&u32_to_basicreal(self.low_msg_num).to_le_bytes());
+        data.extend(&u32_to_basicreal(self.active_msgs).to_le_bytes());
+        let callers = if basicreal_to_u32(self.raw_callers) == self.callers {
+            self.raw_callers
+        } else {
+            u32_to_basicreal(self.callers)
+        };
+        data.extend(&callers.to_le_bytes());
         data.extend(&self.lock_status);
         file.write_all(&data)?;
         Ok(())
@@ -0,0 +1,138 @@
+use bstr::BString;
+
+use crate::message_base::{flags, Message, MessageBase, MessageBaseFormat, MessageBaseWriter};
+
+use super::{
+    message_header::{ExtendedHeaderInformation, PCBoardExtendedHeader, MSG_INACTIVE},
+    MessageStatus, PCBoardMessage, PCBoardMessageBase,
+};
+
+impl MessageBase for PCBoardMessageBase {
+    fn format(&self) -> MessageBaseFormat {
+        MessageBaseFormat::PCBoard
+    }
+
+    fn active_messages(&self) -> u32 {
+        PCBoardMessageBase::active_messages(self)
+    }
+
+    fn get_message(&self, number: u32) -> crate::Result<Message> {
+        Ok(self.read_message(number)?.into())
+    }
+
+    fn messages(&self) -> Box<dyn Iterator<Item = crate::Result<Message>> + '_> {
+        Box::new(self.iter().map(|msg| msg.map(Message::from)))
+    }
+}
+
+impl MessageBaseWriter for PCBoardMessageBase {
+    fn write_message(&mut self, msg: &Message) -> crate::Result<u32> {
+        PCBoardMessageBase::write_message(self, &PCBoardMessage::from(msg))
+    }
+
+    fn next_message_number(&self) -> crate::Result<u32> {
+        Ok(PCBoardMessageBase::next_message_number(self))
+    }
+
+    fn set_next_message_number(&mut self, number: u32) -> crate::Result<()> {
+        PCBoardMessageBase::set_next_message_number(self, number)
+    }
+}
+
+impl From<&Message> for PCBoardMessage {
+    fn from(msg: &Message) -> Self {
+        let is_read = msg.has_flag(flags::READ);
+        let status = if msg.is_private() {
+            if is_read {
+                b'+'
+            } else {
+                b'*'
+            }
+        } else if !msg.password.is_empty() {
+            if is_read {
+                b'^'
+            } else {
+                b'%'
+            }
+        } else if is_read {
+            b'-'
+        } else {
+            b' '
+        };
+
+        let mut res = PCBoardMessage::new()
+            .with_from(msg.from.clone())
+            .with_to(msg.to.clone())
+            .with_subject(msg.subject.clone())
+            .with_date_time(msg.date_written)
+            .with_reply_to(msg.reply_to)
+            .with_password(msg.password.clone())
+            .with_status(status)
+            .with_text(msg.text.clone());
+        if msg.is_deleted() {
+            res.header.active_flag = MSG_INACTIVE;
+        }
+        for file in &msg.attachments {
+            res = res.with_extended_header(PCBoardExtendedHeader::new(
+                ExtendedHeaderInformation::Attach,
+                file.clone(),
+            ));
+        }
+        if msg.has_flag(flags::RECEIPT_REQUEST) {
+            res = res.with_extended_header(PCBoardExtendedHeader::new(
+                ExtendedHeaderInformation::Reqrr,
+                BString::default(),
+            ));
+        }
+        res
+    }
+}
+
+impl From<PCBoardMessage> for Message {
+    fn from(msg: PCBoardMessage) -> Self {
+        let mut flags = 0;
+        if matches!(
+            msg.get_status(),
+            MessageStatus::Private | MessageStatus::CommentToSysop
+        ) {
+            flags |= flags::PRIVATE;
+        }
+        if msg.is_read() {
+            flags |= flags::READ;
+        }
+        if msg.is_deleted() {
+            flags |= flags::DELETED;
+        }
+        if msg.header.has_attach() {
+            flags |= flags::FILE_ATTACH;
+        }
+        if msg.header.has_reqrr() {
+            flags |= flags::RECEIPT_REQUEST;
+        }
+
+        let mut res = Message {
+            number: msg.header.msg_number,
+            date_written: msg.header.date_time(),
+            flags,
+            reply_to: msg.header.reply_to,
+            from: msg.header.from_field,
+            to: msg.header.to_field,
+            subject: msg.header.subj_field,
+            password: msg.header.password,
+            text: msg.text,
+            ..Default::default()
+        };
+        for ext in msg.extended_header {
+            match ext.info {
+                ExtendedHeaderInformation::To => res.to = ext.content,
+                ExtendedHeaderInformation::From => res.from = ext.content,
+                ExtendedHeaderInformation::Subject => res.subject = ext.content,
+                ExtendedHeaderInformation::To2 => res.to.extend_from_slice(&ext.content),
+                ExtendedHeaderInformation::From2 => res.from.extend_from_slice(&ext.content),
+                ExtendedHeaderInformation::Attach => res.attachments.push(ext.content),
+                _ => {}
+            }
+        }
+        res
+    }
+}
@@ -1,10 +1,10 @@
 use bstr::BString;
 use chrono::{Datelike, Local, NaiveTime};
 
-use super::{convert_pcboard_str, PCBoardError};
+use super::{convert_pcboard_str, gen_string, PCBoardError, PCB_TXT_EOL};
 use crate::{
     pcboard::{DATE_LEN, FROM_TO_LEN, PASSWORD_LEN, TIME_LEN},
-    util::basic_real::basicreal_to_u32,
+    util::basic_real::{basicreal_to_u32, u32_to_basicreal},
 };
 use std::{
     fs::File,
@@ -82,7 +82,7 @@ pub struct PCBoardMessageHeader {
     pub net_tag: u8,
 }
 
-mod extended_status {
+pub(crate) mod extended_status {
     /// Extended header 'TO' is defined
     pub const TO: u8 = 1;
     /// Extended header 'FROM' is defined
@@ -231,9 +231,53 @@ impl PCBoardMessageHeader {
     pub(crate) fn is_deleted(&self) -> bool {
         self.active_flag != MSG_ACTIVE
     }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.push(self.status);
+        buf.extend(u32_to_basicreal(self.msg_number).to_le_bytes());
+        buf.extend(u32_to_basicreal(self.reply_to).to_le_bytes());
+        buf.push(self.num_blocks);
+        buf.extend(gen_string(self.date_time.as_bytes(), DATE_LEN + TIME_LEN));
+        buf.extend(gen_string(&self.to_field, FROM_TO_LEN));
+        buf.extend(u32_to_basicreal(self.reply_date).to_le_bytes());
+        buf.extend(gen_string(self.reply_time.as_bytes(), TIME_LEN));
+        buf.push(self.reply_status);
+        buf.extend(gen_string(&self.from_field, FROM_TO_LEN));
+        buf.extend(gen_string(&self.subj_field, FROM_TO_LEN));
+        buf.extend(gen_string(&self.password, PASSWORD_LEN));
+        buf.push(self.active_flag);
+        buf.push(self.echo_flag);
+        buf.extend(self.reserved);
+        buf.push(self.extended_status);
+        buf.push(self.net_tag);
+    }
+}
+
+impl Default for PCBoardMessageHeader {
+    fn default() -> Self {
+        Self {
+            status: b' ',
+            msg_number: 0,
+            reply_to: 0,
+            num_blocks: 1,
+            date_time: String::new(),
+            to_field: BString::default(),
+            reply_date: 0,
+            reply_time: String::new(),
+            reply_status: NOT_REPLIED,
+            from_field: BString::default(),
+            subj_field: BString::default(),
+            password: BString::default(),
+            active_flag: MSG_ACTIVE,
+            echo_flag: NOECHO,
+            reserved: [0; 4],
+            extended_status: 0,
+            net_tag: 0,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExtendedHeaderInformation {
     To,
     From,
@@ -345,6 +389,7 @@ impl ExtendedHeaderInformation {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct PCBoardExtendedHeader {
     pub info: ExtendedHeaderInformation,
     pub content: BString,
@@ -353,9 +398,18 @@ pub struct PCBoardExtendedHeader {
 }
 
 impl PCBoardExtendedHeader {
-    // const ID:u16 = 0x40FF;
+    const ID: u16 = 0x40FF;
     const FUNC_LEN: usize = 7;
-    const DESC_LEN: usize = 60;
+    pub const DESC_LEN: usize = 60;
+    pub const HEADER_SIZE: usize = 2 + Self::FUNC_LEN + 1 + Self::DESC_LEN + 2;
+
+    pub fn new(info: ExtendedHeaderInformation, content: BString) -> Self {
+        Self {
+            info,
+            content,
+            status: b'N',
+        }
+    }
 
     pub fn read(&self) -> bool {
         self.status == b'R'
@@ -364,7 +418,7 @@ impl PCBoardExtendedHeader {
     pub fn deserialize(buf: &[u8]) -> crate::Result<Self> {
         // let _id = u16::from_le_bytes([buf[0], buf[1]]);
         let mut i = 2;
-        let function = ExtendedHeaderInformation::from_data(&buf[i..i + Self::FUNC_LEN]).unwrap();
+        let function = ExtendedHeaderInformation::from_data(&buf[i..i + Self::FUNC_LEN])?;
         i += Self::FUNC_LEN + 1; // skip ':'
 
         let content = convert_pcboard_str(&buf[i..i + Self::DESC_LEN]);
@@ -377,4 +431,13 @@ impl PCBoardExtendedHeader {
             status,
         })
     }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend(Self::ID.to_le_bytes());
+        buf.extend(self.info.to_str().as_bytes());
+        buf.push(b':');
+        buf.extend(gen_string(&self.content, Self::DESC_LEN));
+        buf.push(self.status);
+        buf.push(PCB_TXT_EOL);
+    }
 }
@@ -5,7 +5,14 @@ use std::{
 
 use bstr::BString;
 
-use crate::pcboard::FROM_TO_LEN;
+use chrono::NaiveDate;
+
+use crate::pcboard::{gen_string, FROM_TO_LEN};
+
+use super::message_header::{PCBoardMessageHeader, MSG_INACTIVE};
+
+/// Index status of a deleted message, active messages use the status of the message header.
+pub const STATUS_DELETED: u8 = MSG_INACTIVE;
 
 #[derive(Clone, Debug)]
 pub struct PCBoardMessageIndex {
@@ -21,6 +28,27 @@ pub struct PCBoardMessageIndex {
 impl PCBoardMessageIndex {
     pub const HEADER_SIZE: usize = 4 + 4 + 25 + 25 + 1 + 2 + 3;
 
+    /// Index record of a message stored at `offset` in the message file.
+    pub fn new(offset: u32, header: &PCBoardMessageHeader) -> Self {
+        Self {
+            offset,
+            num: header.msg_number,
+            to: header.to_field.clone(),
+            from: header.from_field.clone(),
+            status: if header.is_deleted() {
+                STATUS_DELETED
+            } else {
+                header.status
+            },
+            date: Self::date_to_index(header.date_time().date()),
+            reserved: [0; 3],
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.status == STATUS_DELETED
+    }
+
     pub fn read(file: &mut BufReader<File>) -> crate::Result<Self> {
         let data = &mut [0; Self::HEADER_SIZE];
         file.read_exact(data)?;
@@ -45,17 +73,6 @@ impl PCBoardMessageIndex {
         })
     }
 
-    /*
-    pub fn load(file: &str) -> crate::Result<Vec<Self>> {
-        let buf = fs::read(file)?;
-        let mut cursor = Cursor::new(&buf);
-        let mut messages = Vec::new();
-        while cursor.position() < cursor.get_ref().len() as u64 {
-            messages.push(Self::deserialize(&mut cursor)?);
-        }
-        Ok(messages)
-    }
-
     pub fn serialize(&self, buf: &mut Vec<u8>) {
         buf.extend(&self.offset.to_le_bytes());
         buf.extend(&self.num.to_le_bytes());
@@ -65,5 +82,11 @@ impl PCBoardMessageIndex {
         buf.push(self.status);
         buf.extend(&self.date.to_le_bytes());
         buf.extend(&self.reserved);
-    }*/
+    }
+
+    /// Converts a date to the index date format (days since 12-31-1899).
+    pub fn date_to_index(date: NaiveDate) -> u16 {
+        let base = NaiveDate::from_ymd_opt(1899, 12, 31).unwrap();
+        (date - base).num_days().clamp(0, u16::MAX as i64) as u16
+    }
 }
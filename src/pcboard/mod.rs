@@ -1,23 +1,36 @@
 use std::{
     fs::{self, File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Seek},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
-use bstr::BString;
+use bstr::{BString, ByteSlice};
+use chrono::NaiveDateTime;
 use thiserror::Error;
 
-use crate::util::basic_real::basicreal_to_u32;
+use crate::{
+    message_base::MessageBaseError,
+    util::{
+        basic_real::{basicreal_to_u32, u32_to_basicreal},
+        file_lock::{lock_region, unlock_region},
+    },
+};
 
 use self::{
     base_header::PCBoardMessageBaseHeader,
-    message_header::{PCBoardExtendedHeader, PCBoardMessageHeader},
+    message_header::{
+        extended_status, ExtendedHeaderInformation, PCBoardExtendedHeader, PCBoardMessageHeader,
+        MSG_ACTIVE, MSG_INACTIVE,
+    },
     message_index::PCBoardMessageIndex,
 };
 
 mod base_header;
+mod message_base;
 pub mod message_header;
 mod message_index;
+pub mod pack;
+pub mod reindex;
 
 #[cfg(test)]
 mod tests;
@@ -34,6 +47,18 @@ pub enum PCBoardError {
 
     #[error("Unknown extended header: {0}")]
     UnknownExtendedHeader(BString),
+
+    #[error("Message number {0} is lower than the next message number {1}")]
+    MessageNumberInUse(u32, u32),
+
+    #[error("Message base is locked")]
+    MessageBaseLocked,
+
+    #[error("Message too long ({0} blocks), at most 255 blocks are allowed")]
+    MessageTooLong(usize),
+
+    #[error("Invalid message header at offset {0}")]
+    InvalidMessageHeader(u64),
 }
 
 mod extensions {
@@ -47,23 +72,20 @@ mod extensions {
 /// PCBoard strings contain trailing spaces that need to be removed.
 pub(crate) fn convert_pcboard_str(buf: &[u8]) -> BString {
     let mut str = BString::from(buf);
-    while str.ends_with(&[b' ']) {
+    while str.ends_with(b" ") {
         str.pop();
     }
     str
 }
 
-fn _gen_string(str: &str, num: usize) -> Vec<u8> {
-    let mut buf = Vec::new();
-    for c in str.chars().take(num) {
-        buf.push(c as u8);
-    }
-    while buf.len() < num {
-        buf.push(b' ');
-    }
+/// Left justifies a field and fills it with spaces.
+pub(crate) fn gen_string(data: &[u8], len: usize) -> Vec<u8> {
+    let mut buf = data[..data.len().min(len)].to_vec();
+    buf.resize(len, b' ');
     buf
 }
 
+#[derive(Clone, Default)]
 pub struct PCBoardMessage {
     pub header: PCBoardMessageHeader,
     pub extended_header: Vec<PCBoardExtendedHeader>,
@@ -71,6 +93,133 @@ pub struct PCBoardMessage {
 }
 
 impl PCBoardMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names longer than 25 chars are stored in FROM/FROM2 extended headers.
+    pub fn with_from(mut self, from: BString) -> Self {
+        self.header.from_field = self.set_long_field(
+            ExtendedHeaderInformation::From,
+            Some(ExtendedHeaderInformation::From2),
+            from,
+        );
+        self
+    }
+
+    /// Names longer than 25 chars are stored in TO/TO2 extended headers.
+    pub fn with_to(mut self, to: BString) -> Self {
+        self.header.to_field = self.set_long_field(
+            ExtendedHeaderInformation::To,
+            Some(ExtendedHeaderInformation::To2),
+            to,
+        );
+        self
+    }
+
+    /// Subjects longer than 25 chars are stored in an extended header (max. 60 chars).
+    pub fn with_subject(mut self, subject: BString) -> Self {
+        self.header.subj_field =
+            self.set_long_field(ExtendedHeaderInformation::Subject, None, subject);
+        self
+    }
+
+    pub fn with_date_time(mut self, date_time: NaiveDateTime) -> Self {
+        self.header.date_time = date_time.format("%m-%d-%y%H:%M").to_string();
+        self
+    }
+
+    pub fn with_reply_to(mut self, reply_to: u32) -> Self {
+        self.header.reply_to = reply_to;
+        self
+    }
+
+    pub fn with_password(mut self, password: BString) -> Self {
+        self.header.password = password;
+        self
+    }
+
+    /// See `PCBoardMessageHeader::status`
+    pub fn with_status(mut self, status: u8) -> Self {
+        self.header.status = status;
+        self
+    }
+
+    /// Lines are separated by '\n'
+    pub fn with_text(mut self, text: BString) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Adds an extended header, e.g. an ATTACH header.
+    pub fn with_extended_header(mut self, header: PCBoardExtendedHeader) -> Self {
+        self.header.extended_status |= extended_status_flag(header.info);
+        self.extended_header.push(header);
+        self
+    }
+
+    /// Returns the header field value and replaces the extended headers for long values.
+    ///
+    /// # Remarks
+    /// An extended header holds 60 chars, the rest goes into the continuation header.
+    fn set_long_field(
+        &mut self,
+        info: ExtendedHeaderInformation,
+        continuation: Option<ExtendedHeaderInformation>,
+        value: BString,
+    ) -> BString {
+        self.extended_header
+            .retain(|h| h.info != info && Some(h.info) != continuation);
+        self.header.extended_status &= !extended_status_flag(info);
+        if value.len() <= FROM_TO_LEN {
+            return value;
+        }
+        let field = BString::from(&value[..FROM_TO_LEN]);
+        self.header.extended_status |= extended_status_flag(info);
+        let (first, rest) = value.split_at(value.len().min(PCBoardExtendedHeader::DESC_LEN));
+        self.extended_header
+            .push(PCBoardExtendedHeader::new(info, first.into()));
+        if let Some(continuation) = continuation {
+            if !rest.is_empty() {
+                self.extended_header
+                    .push(PCBoardExtendedHeader::new(continuation, rest.into()));
+            }
+        }
+        field
+    }
+
+    /// Cuts off the text so the message fits into 255 blocks.
+    /// Returns true if the text was shortened.
+    pub fn truncate_text(&mut self) -> bool {
+        let max_len = ((u8::MAX as usize - 1) * PCBoardMessageHeader::HEADER_SIZE)
+            .saturating_sub(self.extended_header.len() * PCBoardExtendedHeader::HEADER_SIZE);
+        if self.text.len() <= max_len {
+            return false;
+        }
+        self.text.truncate(max_len);
+        true
+    }
+
+    /// Serializes header, extended headers and text blocks and sets the number of blocks.
+    pub fn serialize(&mut self) -> crate::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        for header in &self.extended_header {
+            header.serialize(&mut body);
+        }
+        body.extend(self.text.replace([b'\n'], PCB_TXT_EOL_PTR));
+        let num_blocks = body.len().div_ceil(PCBoardMessageHeader::HEADER_SIZE) + 1;
+        if num_blocks > u8::MAX as usize {
+            return Err(PCBoardError::MessageTooLong(num_blocks).into());
+        }
+        body.resize((num_blocks - 1) * PCBoardMessageHeader::HEADER_SIZE, b' ');
+        self.header.num_blocks = num_blocks as u8;
+
+        let mut res = Vec::with_capacity(num_blocks * PCBoardMessageHeader::HEADER_SIZE);
+        self.header.serialize(&mut res);
+        res.extend(body);
+        Ok(res)
+    }
+
     pub fn read(file: &mut BufReader<File>) -> crate::Result<Self> {
         let header = PCBoardMessageHeader::read(file)?;
         let mut buf = vec![0; 128 * ((header.num_blocks as usize).saturating_sub(1))];
@@ -79,7 +228,9 @@ impl PCBoardMessage {
 
         let mut extended_header = Vec::new();
         while i < buf.len() {
-            if buf[i] == 0xFF && buf[i + 1] == 0x40 {
+            if buf[i..].starts_with(&[0xFF, 0x40])
+                && buf.len() - i >= PCBoardExtendedHeader::HEADER_SIZE
+            {
                 extended_header.push(PCBoardExtendedHeader::deserialize(&buf[i..])?);
                 i += 0x48;
                 continue;
@@ -120,6 +271,19 @@ impl PCBoardMessage {
     }
 }
 
+/// Extended status bit of an extended header type
+fn extended_status_flag(info: ExtendedHeaderInformation) -> u8 {
+    match info {
+        ExtendedHeaderInformation::To => extended_status::TO,
+        ExtendedHeaderInformation::From => extended_status::FROM,
+        ExtendedHeaderInformation::Subject => extended_status::SUBJ,
+        ExtendedHeaderInformation::List => extended_status::LIST,
+        ExtendedHeaderInformation::Attach => extended_status::ATTACH,
+        ExtendedHeaderInformation::Reqrr => extended_status::REQRR,
+        _ => 0,
+    }
+}
+
 pub const PCB_TXT_EOL: u8 = 0xE3;
 pub const PCB_TXT_EOL_PTR: &[u8] = &[PCB_TXT_EOL];
 
@@ -148,6 +312,8 @@ pub enum MessageStatus {
 pub struct PCBoardMessageBase {
     file_name: PathBuf,
     header_info: PCBoardMessageBaseHeader,
+    /// The lock is held by this instance
+    has_lock: bool,
 }
 
 impl PCBoardMessageBase {
@@ -157,14 +323,57 @@ impl PCBoardMessageBase {
         Ok(Self {
             file_name: file_name.as_ref().into(),
             header_info,
+            has_lock: false,
         })
     }
 
+    /// Checks if the file starts with a PCBoard message base header block.
+    pub fn is_message_base<P: AsRef<Path>>(file_name: P) -> bool {
+        let mut data = [0; PCBoardMessageBaseHeader::BLOCK_SIZE];
+        File::open(file_name).is_ok_and(|mut file| file.read_exact(&mut data).is_ok())
+            && PCBoardMessageBaseHeader::is_valid(&data)
+    }
+
+    /// Creates a new empty message base and an empty .IDX file.
+    pub fn create<P: AsRef<Path>>(file_name: P) -> crate::Result<Self> {
+        let mut writer = BufWriter::new(File::create(&file_name)?);
+        PCBoardMessageBaseHeader::new().create(&mut writer)?;
+        fs::write(file_name.as_ref().with_extension(extensions::INDEX), "")?;
+        Self::open(file_name)
+    }
+
     fn write_base_header(&self) -> crate::Result<()> {
-        let header_file = OpenOptions::new().write(true).open(&self.file_name)?;
-        let mut writer = BufWriter::new(header_file);
-        self.header_info.write_header_to(&mut writer)?;
-        Ok(())
+        self.with_header_block(|file| {
+            let mut writer = BufWriter::new(file);
+            self.header_info.write_header_to(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        })
+    }
+
+    /// Runs `f` with the message file positioned at the header block,
+    /// while holding an OS level lock on the header block.
+    ///
+    /// # Remarks
+    /// The lock makes checking and setting the "LOCKED" marker atomic between nodes.
+    fn with_header_block<T>(
+        &self,
+        f: impl FnOnce(&mut File) -> crate::Result<T>,
+    ) -> crate::Result<T> {
+        let block_size = PCBoardMessageBaseHeader::BLOCK_SIZE as u64;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.file_name)?;
+        lock_region(&file, 0, block_size, true)?;
+        let res = file
+            .seek(SeekFrom::Start(0))
+            .map_err(Into::into)
+            .and_then(|_| f(&mut file));
+        let unlock_res = unlock_region(&file, 0, block_size);
+        let res = res?;
+        unlock_res?;
+        Ok(res)
     }
 
     pub fn is_locked(&mut self) -> crate::Result<bool> {
@@ -172,16 +381,180 @@ impl PCBoardMessageBase {
         Ok(self.header_info.is_locked())
     }
 
+    /// Sets the "LOCKED" marker of the message base header,
+    /// fails if the message base is locked by someone else.
     pub fn lock(&mut self) -> crate::Result<()> {
-        self.header_info.lock();
-        self.write_base_header()
+        let has_lock = self.has_lock;
+        self.header_info = self.with_header_block(|file| {
+            let mut header_info = PCBoardMessageBaseHeader::load(file)?;
+            if header_info.is_locked() && !has_lock {
+                return Err(PCBoardError::MessageBaseLocked.into());
+            }
+            header_info.lock();
+            file.seek(SeekFrom::Start(0))?;
+            header_info.write_header_to(file)?;
+            Ok(header_info)
+        })?;
+        self.has_lock = true;
+        Ok(())
     }
 
     pub fn unlock(&mut self) -> crate::Result<()> {
         self.header_info.unlock();
+        self.write_base_header()?;
+        self.has_lock = false;
+        Ok(())
+    }
+
+    /// Runs `f` while holding the lock, fails if another process holds the lock.
+    fn with_lock<T>(&mut self, f: impl FnOnce(&mut Self) -> crate::Result<T>) -> crate::Result<T> {
+        if self.has_lock {
+            return f(self);
+        }
+        self.lock()?;
+        let res = f(self);
+        let unlock_res = self.unlock();
+        match res {
+            Ok(res) => unlock_res.map(|_| res),
+            Err(err) => {
+                if let Err(unlock_err) = unlock_res {
+                    log::error!("Error unlocking message base: {}", unlock_err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Appends a message and returns the assigned message number.
+    ///
+    /// # Remarks
+    /// Updates the .IDX file, the .NDX file (if present) and the message base header.
+    /// Fails if the message base is locked by someone else.
+    pub fn write_message(&mut self, msg: &PCBoardMessage) -> crate::Result<u32> {
+        self.with_lock(|base| base.append_message(msg))
+    }
+
+    /// The number the next written message gets.
+    pub fn next_message_number(&self) -> u32 {
+        self.header_info.high_msg_num + 1
+    }
+
+    /// Skips message numbers so the next written message gets `number`.
+    pub fn set_next_message_number(&mut self, number: u32) -> crate::Result<()> {
+        self.with_lock(|base| {
+            let next = base.next_message_number();
+            if number < next {
+                return Err(PCBoardError::MessageNumberInUse(number, next).into());
+            }
+            base.header_info.high_msg_num = number - 1;
+            base.write_base_header()
+        })
+    }
+
+    fn append_message(&mut self, msg: &PCBoardMessage) -> crate::Result<u32> {
+        let number = self.header_info.high_msg_num + 1;
+        let mut msg = msg.clone();
+        msg.header.msg_number = number;
+        let data = msg.serialize()?;
+
+        let mut file = OpenOptions::new().write(true).open(&self.file_name)?;
+        let end = file.seek(SeekFrom::End(0))?;
+        // messages start at block boundaries
+        let offset = end
+            .max(PCBoardMessageHeader::HEADER_SIZE as u64)
+            .next_multiple_of(PCBoardMessageHeader::HEADER_SIZE as u64);
+        if offset != end {
+            file.write_all(&vec![b' '; (offset - end) as usize])?;
+        }
+        file.write_all(&data)?;
+
+        let index = PCBoardMessageIndex::new(offset as u32, &msg.header);
+        self.write_index_record(&index)?;
+        self.write_old_index_record(number, offset)?;
+
+        if self.header_info.low_msg_num == 0 {
+            self.header_info.low_msg_num = number;
+        }
+        self.header_info.high_msg_num = number;
+        if !msg.is_deleted() {
+            self.header_info.active_msgs += 1;
+        }
+        self.write_base_header()?;
+        Ok(number)
+    }
+
+    /// Marks a message as inactive in the message header and the .IDX file.
+    ///
+    /// # Remarks
+    /// The message stays in the message file until the message base gets packed.
+    pub fn delete_message(&mut self, num: u32) -> crate::Result<()> {
+        self.with_lock(|base| base.set_active(num, false))
+    }
+
+    /// Recovers a deleted message
+    /// The opposite of `delete_message`
+    pub fn restore_message(&mut self, num: u32) -> crate::Result<()> {
+        self.with_lock(|base| base.set_active(num, true))
+    }
+
+    fn set_active(&mut self, num: u32, active: bool) -> crate::Result<()> {
+        let index = self.read_index_record(num)?;
+        let mut reader = BufReader::new(File::open(&self.file_name)?);
+        reader.seek(SeekFrom::Start(index.offset as u64))?;
+        let mut header = PCBoardMessageHeader::read(&mut reader)?;
+        if header.is_deleted() != active {
+            return Ok(());
+        }
+        header.active_flag = if active { MSG_ACTIVE } else { MSG_INACTIVE };
+
+        let mut buf = Vec::with_capacity(PCBoardMessageHeader::HEADER_SIZE);
+        header.serialize(&mut buf);
+        let mut file = OpenOptions::new().write(true).open(&self.file_name)?;
+        file.seek(SeekFrom::Start(index.offset as u64))?;
+        file.write_all(&buf)?;
+        self.write_index_record(&PCBoardMessageIndex::new(index.offset, &header))?;
+
+        if active {
+            self.header_info.active_msgs += 1;
+        } else {
+            self.header_info.active_msgs = self.header_info.active_msgs.saturating_sub(1);
+        }
         self.write_base_header()
     }
 
+    fn write_index_record(&self, index: &PCBoardMessageIndex) -> crate::Result<()> {
+        let idx_file_name = self.file_name.with_extension(extensions::INDEX);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(idx_file_name)?;
+        let pos = (index.num as u64 - 1) * PCBoardMessageIndex::HEADER_SIZE as u64;
+        let end = file.seek(SeekFrom::End(0))?;
+        if end < pos {
+            // unused records are zero filled
+            file.write_all(&vec![0; (pos - end) as usize])?;
+        }
+        let mut buf = Vec::with_capacity(PCBoardMessageIndex::HEADER_SIZE);
+        index.serialize(&mut buf);
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// The old index only gets updated if it exists.
+    fn write_old_index_record(&self, number: u32, offset: u64) -> crate::Result<()> {
+        let old_idx_file_name = self.file_name.with_extension(extensions::OLD_INDEX);
+        if !old_idx_file_name.exists() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().write(true).open(old_idx_file_name)?;
+        let record = (offset / PCBoardMessageHeader::HEADER_SIZE as u64) as u32 + 1;
+        file.seek(SeekFrom::Start((number as u64 - 1) * 4))?;
+        file.write_all(&u32_to_basicreal(record).to_le_bytes())?;
+        Ok(())
+    }
+
     /// Number of active (not deleted) msgs  
     pub fn active_messages(&self) -> u32 {
         self.header_info.active_msgs
@@ -208,15 +581,29 @@ impl PCBoardMessageBase {
             )
             .into());
         }
+        let header = self.read_index_record(num)?;
+        let mut file = BufReader::new(File::open(&self.file_name)?);
+        file.seek(std::io::SeekFrom::Start(header.offset as u64))?;
+        PCBoardMessage::read(&mut file)
+    }
+
+    fn read_index_record(&self, num: u32) -> crate::Result<PCBoardMessageIndex> {
+        if num == 0 {
+            return Err(MessageBaseError::MessageNotFound(num).into());
+        }
         let idx_file_name = self.file_name.with_extension(extensions::INDEX);
         let mut reader = BufReader::new(File::open(idx_file_name)?);
-        reader.seek(std::io::SeekFrom::Start(
+        reader.seek(SeekFrom::Start(
             (num as u64 - 1) * PCBoardMessageIndex::HEADER_SIZE as u64,
         ))?;
-        let header = PCBoardMessageIndex::read(&mut reader)?;
-        let mut file = BufReader::new(File::open(&self.file_name)?);
-        file.seek(std::io::SeekFrom::Start(header.offset as u64))?;
-        PCBoardMessage::read(&mut file)
+        let Ok(header) = PCBoardMessageIndex::read(&mut reader) else {
+            return Err(MessageBaseError::MessageNotFound(num).into());
+        };
+        // skipped message numbers have empty index records
+        if header.num != num {
+            return Err(MessageBaseError::MessageNotFound(num).into());
+        }
+        Ok(header)
     }
 
     pub fn read_old_index(&self) -> crate::Result<Vec<u32>> {
@@ -238,6 +625,31 @@ impl PCBoardMessageBase {
         Ok(res)
     }
 
+    /// Reads the old .NDX file and returns message number and message offset pairs.
+    ///
+    /// # Remarks
+    /// Unlike `read_old_index` the whole file is read, empty records
+    /// (skipped, removed or unused message numbers) are left out.
+    pub fn read_old_index_records(&self) -> crate::Result<Vec<(u32, u32)>> {
+        let old_idx_file_name = self.file_name.with_extension(extensions::OLD_INDEX);
+
+        let mut res = Vec::new();
+        let bytes = fs::read(old_idx_file_name)?;
+
+        let mut data = &bytes[..];
+        let mut number = 0;
+        while data.len() >= 4 {
+            convert_u32!(record, data);
+            number += 1;
+            let Some(block) = basicreal_to_u32(record).checked_sub(1) else {
+                continue;
+            };
+            res.push((number, block.saturating_mul(128)));
+        }
+
+        Ok(res)
+    }
+
     pub fn read_index(&self) -> crate::Result<Vec<PCBoardMessageIndex>> {
         let idx_file_name = self.file_name.with_extension(extensions::INDEX);
 
@@ -252,15 +664,7 @@ impl PCBoardMessageBase {
     }
 
     pub fn iter(&self) -> impl Iterator<Item = crate::Result<PCBoardMessage>> {
-        let idx_file_name = self.file_name.clone();
-        let mut f = File::open(idx_file_name).unwrap();
-        let size = f.metadata().unwrap().len();
-
-        f.seek(std::io::SeekFrom::Start(128)).unwrap();
-        PCBoardMessageIter {
-            reader: BufReader::new(f),
-            size,
-        }
+        PCBoardMessageIter::open(&self.file_name).unwrap()
     }
 }
 
@@ -269,6 +673,19 @@ struct PCBoardMessageIter {
     size: u64,
 }
 
+impl PCBoardMessageIter {
+    fn open(file_name: &Path) -> crate::Result<Self> {
+        let mut f = File::open(file_name)?;
+        let size = f.metadata()?.len();
+
+        f.seek(SeekFrom::Start(PCBoardMessageBaseHeader::BLOCK_SIZE as u64))?;
+        Ok(Self {
+            reader: BufReader::new(f),
+            size,
+        })
+    }
+}
+
 impl Iterator for PCBoardMessageIter {
     type Item = crate::Result<PCBoardMessage>;
 
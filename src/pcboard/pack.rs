@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::util::basic_real::u32_to_basicreal;
+
+use super::{
+    base_header::PCBoardMessageBaseHeader, extensions, message_header::PCBoardMessageHeader,
+    message_index::PCBoardMessageIndex, reindex::is_valid_header, PCBoardError, PCBoardMessageBase,
+};
+
+/// Outcome of `PCBoardMessageBase::pack`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PCBoardPackReport {
+    /// Message numbers that were removed from the message base.
+    pub removed_messages: Vec<u32>,
+    /// Number of bytes the message file shrunk.
+    pub reclaimed_bytes: u64,
+}
+
+const TMP_SUFFIX: &str = "tmp";
+
+impl PCBoardMessageBase {
+    /// Removes all deleted messages from the message file and rewrites the indexes.
+    ///
+    /// # Remarks
+    /// The messages are taken from a scan of the message file, not from the .IDX file.
+    /// Messages are not renumbered, index records of removed messages are zero filled.
+    /// The lowest message number is moved to the first remaining message.
+    /// The .NDX file is only rewritten if it exists.
+    ///
+    /// Packing fails without changes if a block that should start a message doesn't contain
+    /// a valid message header, `reindex` reports these blocks.
+    /// The packed message file and indexes are written and synced to temporary files first,
+    /// which then replace the original files. On failure the temporary files are removed.
+    /// If replacing the indexes fails, `reindex` rebuilds them from the message file.
+    pub fn pack(&mut self) -> crate::Result<PCBoardPackReport> {
+        self.with_lock(|base| base.pack_messages())
+    }
+
+    fn pack_messages(&mut self) -> crate::Result<PCBoardPackReport> {
+        let tmp_file_name = self.file_name.with_extension(TMP_SUFFIX);
+        let mut indexes = vec![self.file_name.with_extension(extensions::INDEX)];
+        let old_idx_file_name = self.file_name.with_extension(extensions::OLD_INDEX);
+        if old_idx_file_name.exists() {
+            indexes.push(old_idx_file_name);
+        }
+
+        let (report, kept) = match self.write_packed_files(&tmp_file_name, &indexes) {
+            Ok(res) => res,
+            Err(err) => {
+                for file_name in indexes.iter().map(|f| tmp_index_name(f)) {
+                    let _ = fs::remove_file(file_name);
+                }
+                let _ = fs::remove_file(&tmp_file_name);
+                return Err(err);
+            }
+        };
+
+        // All files are complete, renaming replaces them atomically.
+        fs::rename(&tmp_file_name, &self.file_name)?;
+        for file_name in &indexes {
+            fs::rename(tmp_index_name(file_name), file_name)?;
+        }
+
+        self.header_info.low_msg_num = kept.keys().next().copied().unwrap_or_default();
+        self.header_info.active_msgs = kept.len() as u32;
+        self.write_base_header()?;
+        Ok(report)
+    }
+
+    /// Writes the packed message file and the new indexes to temporary files.
+    /// Returns the report and the new offsets of the remaining messages.
+    fn write_packed_files(
+        &self,
+        tmp_file_name: &Path,
+        indexes: &[PathBuf],
+    ) -> crate::Result<(PCBoardPackReport, BTreeMap<u32, u64>)> {
+        let block_size = PCBoardMessageHeader::HEADER_SIZE as u64;
+        let mut reader = BufReader::new(File::open(&self.file_name)?);
+        let old_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header_block = vec![0; PCBoardMessageBaseHeader::BLOCK_SIZE];
+        reader.read_exact(&mut header_block)?;
+        let mut writer = BufWriter::new(File::create(tmp_file_name)?);
+        writer.write_all(&header_block)?;
+
+        let mut offset = PCBoardMessageBaseHeader::BLOCK_SIZE as u64;
+        let mut new_offset = offset;
+        let mut kept = BTreeMap::new();
+        let mut idx_records = Vec::new();
+        let mut removed_messages = Vec::new();
+        let mut highest_number = 0;
+        while offset + block_size <= old_size {
+            reader.seek(SeekFrom::Start(offset))?;
+            let header = PCBoardMessageHeader::read(&mut reader)?;
+            let len = header.num_blocks as u64 * block_size;
+            if !is_valid_header(&header, self.header_info.high_msg_num) || offset + len > old_size {
+                return Err(PCBoardError::InvalidMessageHeader(offset).into());
+            }
+            highest_number = highest_number.max(header.msg_number);
+
+            if header.is_deleted() {
+                removed_messages.push(header.msg_number);
+            } else {
+                let mut data = vec![0; len as usize];
+                reader.seek(SeekFrom::Start(offset))?;
+                reader.read_exact(&mut data)?;
+                writer.write_all(&data)?;
+
+                let mut record = Vec::with_capacity(PCBoardMessageIndex::HEADER_SIZE);
+                PCBoardMessageIndex::new(new_offset as u32, &header).serialize(&mut record);
+                idx_records.push((header.msg_number, record));
+                kept.insert(header.msg_number, new_offset);
+                new_offset += len;
+                log::info!("Packed message {}", header.msg_number);
+            }
+            offset += len;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        let idx_len = highest_number as u64 * PCBoardMessageIndex::HEADER_SIZE as u64;
+        write_index_records(
+            &tmp_index_name(&indexes[0]),
+            idx_len,
+            PCBoardMessageIndex::HEADER_SIZE,
+            idx_records,
+        )?;
+        if let Some(old_idx_file_name) = indexes.get(1) {
+            let ndx_len = fs::metadata(old_idx_file_name)?.len();
+            let ndx_records = kept.iter().map(|(number, offset)| {
+                let record = (offset / block_size) as u32 + 1;
+                (*number, u32_to_basicreal(record).to_le_bytes().to_vec())
+            });
+            write_index_records(&tmp_index_name(old_idx_file_name), ndx_len, 4, ndx_records)?;
+        }
+
+        let report = PCBoardPackReport {
+            removed_messages,
+            reclaimed_bytes: old_size.saturating_sub(new_offset),
+        };
+        Ok((report, kept))
+    }
+}
+
+/// Writes index records of `record_size` bytes at the positions of their message numbers
+/// and syncs the file. The file has at least `len` bytes, gaps stay zero filled.
+///
+/// # Remarks
+/// The records are written with seeks, so high message numbers don't need memory.
+pub(super) fn write_index_records(
+    file_name: &Path,
+    len: u64,
+    record_size: usize,
+    records: impl IntoIterator<Item = (u32, Vec<u8>)>,
+) -> crate::Result<()> {
+    let mut file = File::create(file_name)?;
+    file.set_len(len)?;
+    for (number, record) in records {
+        let pos = (number as u64).saturating_sub(1) * record_size as u64;
+        file.seek(SeekFrom::Start(pos))?;
+        file.write_all(&record)?;
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+fn tmp_index_name(file_name: &Path) -> PathBuf {
+    let mut name = file_name.as_os_str().to_owned();
+    name.push(".");
+    name.push(TMP_SUFFIX);
+    name.into()
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, Seek, SeekFrom},
+};
+
+use chrono::NaiveDateTime;
+
+use crate::util::basic_real::u32_to_basicreal;
+
+use super::{
+    base_header::PCBoardMessageBaseHeader,
+    extensions,
+    message_header::{PCBoardMessageHeader, MSG_ACTIVE, MSG_INACTIVE},
+    message_index::PCBoardMessageIndex,
+    pack::write_index_records,
+    PCBoardMessageBase,
+};
+
+/// Size of the old .NDX file
+const OLD_INDEX_SIZE: u64 = 16384;
+
+/// A problem found by `PCBoardMessageBase::reindex`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PCBoardIndexIssue {
+    /// The message file size is not a multiple of the block size
+    PartialBlock(u64),
+
+    /// The block at the offset doesn't contain a readable and plausible message header
+    InvalidHeader { offset: u64 },
+
+    /// The message needs more blocks than the message file contains
+    TruncatedMessage {
+        offset: u64,
+        message_number: u32,
+        num_blocks: u8,
+    },
+
+    /// The message number isn't higher than the number of the previous message
+    MessageNumberOutOfOrder {
+        offset: u64,
+        message_number: u32,
+        previous_number: u32,
+    },
+
+    /// The active messages in the message base header don't match the active messages
+    ActiveMessagesMismatch { header: u32, actual: u32 },
+}
+
+/// Outcome of `PCBoardMessageBase::reindex`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PCBoardReindexReport {
+    /// Messages found in the message file
+    pub messages: u32,
+    /// Active (not deleted) messages found in the message file
+    pub active_messages: u32,
+    pub issues: Vec<PCBoardIndexIssue>,
+}
+
+impl PCBoardReindexReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl PCBoardMessageBase {
+    /// Rebuilds the .IDX file from a scan of the message file and recomputes the header counters.
+    /// If `with_old_index` is set the old .NDX file is rebuilt as well.
+    ///
+    /// # Remarks
+    /// If a message number occurs more than once the last active message wins.
+    /// Only the message headers are read. Blocks without a valid message header are skipped,
+    /// the scan stops at a truncated message.
+    pub fn reindex(&mut self, with_old_index: bool) -> crate::Result<PCBoardReindexReport> {
+        self.with_lock(|base| base.rebuild_indexes(with_old_index))
+    }
+
+    fn rebuild_indexes(&mut self, with_old_index: bool) -> crate::Result<PCBoardReindexReport> {
+        let mut report = PCBoardReindexReport::default();
+        let block_size = PCBoardMessageHeader::HEADER_SIZE as u64;
+        let mut reader = BufReader::new(File::open(&self.file_name)?);
+        let size = reader.seek(SeekFrom::End(0))?;
+        if size % block_size != 0 {
+            report.issues.push(PCBoardIndexIssue::PartialBlock(size));
+        }
+
+        // every message needs at least one block, so higher numbers can't be valid
+        let block_count = u32::try_from(size / block_size).unwrap_or(u32::MAX);
+        let max_number = self.header_info.high_msg_num.saturating_add(block_count);
+
+        let mut headers: BTreeMap<u32, (u64, PCBoardMessageHeader)> = BTreeMap::new();
+        let mut previous_number = 0;
+        let mut offset = PCBoardMessageBaseHeader::BLOCK_SIZE as u64;
+        // only the headers are read, the text blocks are skipped
+        while offset + block_size <= size {
+            reader.seek(SeekFrom::Start(offset))?;
+            let header = PCBoardMessageHeader::read(&mut reader)?;
+            if !is_valid_header(&header, max_number) {
+                report
+                    .issues
+                    .push(PCBoardIndexIssue::InvalidHeader { offset });
+                offset += block_size;
+                continue;
+            }
+            let len = header.num_blocks as u64 * block_size;
+            if offset + len > size {
+                report.issues.push(PCBoardIndexIssue::TruncatedMessage {
+                    offset,
+                    message_number: header.msg_number,
+                    num_blocks: header.num_blocks,
+                });
+                break;
+            }
+
+            if header.msg_number <= previous_number {
+                report
+                    .issues
+                    .push(PCBoardIndexIssue::MessageNumberOutOfOrder {
+                        offset,
+                        message_number: header.msg_number,
+                        previous_number,
+                    });
+            }
+            previous_number = header.msg_number;
+
+            match headers.get(&header.msg_number) {
+                Some((_, old)) if !old.is_deleted() && header.is_deleted() => {}
+                _ => {
+                    headers.insert(header.msg_number, (offset, header));
+                }
+            }
+            offset += len;
+        }
+
+        let mut idx_records = Vec::with_capacity(headers.len());
+        let mut ndx_records = Vec::with_capacity(headers.len());
+        for (number, (offset, header)) in &headers {
+            report.messages += 1;
+            if !header.is_deleted() {
+                report.active_messages += 1;
+            }
+            let mut record = Vec::with_capacity(PCBoardMessageIndex::HEADER_SIZE);
+            PCBoardMessageIndex::new(*offset as u32, header).serialize(&mut record);
+            idx_records.push((*number, record));
+
+            let record = (offset / block_size) as u32 + 1;
+            ndx_records.push((*number, u32_to_basicreal(record).to_le_bytes().to_vec()));
+        }
+
+        // skipped message numbers have empty index records, the files are written
+        // with seeks so a bogus high message number doesn't need memory
+        let idx_len = headers.keys().next_back().copied().unwrap_or_default() as u64
+            * PCBoardMessageIndex::HEADER_SIZE as u64;
+        write_index_records(
+            &self.file_name.with_extension(extensions::INDEX),
+            idx_len,
+            PCBoardMessageIndex::HEADER_SIZE,
+            idx_records,
+        )?;
+        if with_old_index {
+            write_index_records(
+                &self.file_name.with_extension(extensions::OLD_INDEX),
+                OLD_INDEX_SIZE,
+                4,
+                ndx_records,
+            )?;
+        }
+
+        if self.header_info.active_msgs != report.active_messages {
+            report
+                .issues
+                .push(PCBoardIndexIssue::ActiveMessagesMismatch {
+                    header: self.header_info.active_msgs,
+                    actual: report.active_messages,
+                });
+        }
+        self.header_info.low_msg_num = headers.keys().next().copied().unwrap_or_default();
+        if let Some(last) = headers.keys().next_back() {
+            self.header_info.high_msg_num = self.header_info.high_msg_num.max(*last);
+        }
+        self.header_info.active_msgs = report.active_messages;
+        self.write_base_header()?;
+        Ok(report)
+    }
+}
+
+/// Valid values of the status byte of a message header
+const VALID_STATUS: &[u8] = b" -*+~`%^!#$";
+
+/// Checks if a block found while scanning the message file is a plausible message header.
+/// Text blocks can decode to message numbers in the billions, so the number is
+/// checked against `max_number`.
+pub(super) fn is_valid_header(header: &PCBoardMessageHeader, max_number: u32) -> bool {
+    matches!(header.active_flag, MSG_ACTIVE | MSG_INACTIVE)
+        && VALID_STATUS.contains(&header.status)
+        && (1..=max_number).contains(&header.msg_number)
+        && header.num_blocks != 0
+        && NaiveDateTime::parse_from_str(&header.date_time, "%m-%d-%y%H:%M").is_ok()
+}
@@ -1,5 +1,7 @@
 use super::*;
+use chrono::NaiveDate;
 use pretty_assertions::assert_eq;
+use tempfile::TempDir;
 
 #[test]
 fn test_open_base() {
@@ -67,3 +69,389 @@ fn test_iter() {
     }
     assert_eq!(4, base.iter().count());
 }
+
+fn copy_test_base(dir: &Path) -> PathBuf {
+    for ext in ["", ".idx", ".ndx"] {
+        fs::copy(
+            format!("data/pcboard/test{}", ext),
+            dir.join(format!("test{}", ext)),
+        )
+        .unwrap();
+    }
+    dir.join("test")
+}
+
+#[test]
+fn test_write_message() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+
+    let date_time = NaiveDate::from_ymd_opt(2024, 4, 5)
+        .unwrap()
+        .and_hms_opt(22, 20, 0)
+        .unwrap();
+    let subject = "A subject that is too long for the header";
+    let msg = PCBoardMessage::new()
+        .with_from(BString::from("SYSOP"))
+        .with_to(BString::from("ALL"))
+        .with_subject(BString::from(subject))
+        .with_date_time(date_time)
+        .with_reply_to(2)
+        .with_text(BString::from("line 1\nline 2\n"));
+    assert_eq!(base.write_message(&msg).unwrap(), 5);
+    assert_eq!(
+        base.write_message(&msg.clone().with_subject(BString::from("Short")))
+            .unwrap(),
+        6
+    );
+
+    let base = PCBoardMessageBase::open(&path).unwrap();
+    assert_eq!(base.active_messages(), 6);
+    assert_eq!(base.highest_message_number(), 6);
+    assert_eq!(base.lowest_message_number(), 1);
+    // the callers counter isn't touched by writing messages
+    assert_eq!(
+        fs::read(&path).unwrap()[12..16],
+        fs::read("data/pcboard/test").unwrap()[12..16]
+    );
+
+    let read = base.read_message(5).unwrap();
+    assert_eq!(read.header.msg_number, 5);
+    assert_eq!(read.header.reply_to, 2);
+    assert_eq!(read.header.date_time(), date_time);
+    assert!(read.header.has_subject());
+    assert_eq!(read.header.subj_field, &subject[..25]);
+    assert_eq!(read.extended_header[0].content, subject);
+    assert_eq!(read.text.trim_end(), b"line 1\nline 2");
+    assert_eq!(crate::message_base::Message::from(read).subject, subject);
+
+    let read = base.read_message(6).unwrap();
+    assert_eq!(read.header.subj_field, "Short");
+    assert!(!read.header.has_subject());
+    assert!(read.extended_header.is_empty());
+    assert_eq!(base.iter().count(), 6);
+
+    let old_idx = base.read_old_index().unwrap();
+    let new_idx = base.read_index().unwrap();
+    assert_eq!(new_idx.len(), 6);
+    assert_eq!(new_idx[4].num, 5);
+    assert_eq!(new_idx[4].date, new_idx[0].date);
+    assert_eq!(old_idx.len(), 6);
+    for i in 0..old_idx.len() {
+        assert_eq!(old_idx[i], new_idx[i].offset);
+    }
+}
+
+#[test]
+fn test_write_locked() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let mut other = PCBoardMessageBase::open(&path).unwrap();
+
+    other.lock().unwrap();
+    assert!(base.lock().is_err());
+    let msg = PCBoardMessage::new().with_text(BString::from("text"));
+    assert!(base.write_message(&msg).is_err());
+
+    // the lock owner can write
+    assert_eq!(other.write_message(&msg).unwrap(), 5);
+    other.unlock().unwrap();
+    assert_eq!(base.write_message(&msg).unwrap(), 6);
+    assert!(!base.is_locked().unwrap());
+}
+
+#[test]
+fn test_create_base() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("msgs");
+    let mut base = PCBoardMessageBase::create(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap().len(), 128);
+    assert_eq!(fs::read(path.with_extension("idx")).unwrap().len(), 0);
+    assert!(!base.is_locked().unwrap());
+    assert_eq!(base.active_messages(), 0);
+    assert_eq!(base.highest_message_number(), 0);
+    assert_eq!(base.lowest_message_number(), 0);
+    assert_eq!(base.iter().count(), 0);
+
+    let msg = PCBoardMessage::new()
+        .with_from(BString::from("SYSOP"))
+        .with_text(BString::from("first message"));
+    assert_eq!(base.write_message(&msg).unwrap(), 1);
+    assert_eq!(base.write_message(&msg).unwrap(), 2);
+
+    let base = PCBoardMessageBase::open(&path).unwrap();
+    assert_eq!(base.lowest_message_number(), 1);
+    assert_eq!(base.highest_message_number(), 2);
+    assert_eq!(base.read_index().unwrap()[1].offset, 384);
+    assert_eq!(base.read_message(2).unwrap().header.from_field, "SYSOP");
+    assert_eq!(
+        crate::message_base::MessageBaseFormat::detect(&path),
+        Some(crate::message_base::MessageBaseFormat::PCBoard)
+    );
+}
+
+#[test]
+fn test_delete_restore_message() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+
+    base.delete_message(2).unwrap();
+    // deleting twice doesn't change the active messages
+    base.delete_message(2).unwrap();
+    assert_eq!(base.active_messages(), 3);
+    assert!(base.read_message(2).unwrap().is_deleted());
+    assert!(base.read_index().unwrap()[1].is_deleted());
+    assert!(!base.read_index().unwrap()[0].is_deleted());
+    assert!(base.delete_message(9).is_err());
+
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    assert_eq!(base.active_messages(), 3);
+    assert!(!base.is_locked().unwrap());
+    base.restore_message(2).unwrap();
+    base.restore_message(2).unwrap();
+    assert_eq!(base.active_messages(), 4);
+    let msg = base.read_message(2).unwrap();
+    assert!(!msg.is_deleted());
+    assert_eq!(base.read_index().unwrap()[1].status, msg.header.status);
+}
+
+#[test]
+fn test_pack() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let expected = base.read_message(3).unwrap();
+    let old_size = fs::metadata(&path).unwrap().len();
+
+    base.delete_message(1).unwrap();
+    base.delete_message(2).unwrap();
+    let report = base.pack().unwrap();
+    assert_eq!(report.removed_messages, vec![1, 2]);
+    assert_eq!(
+        report.reclaimed_bytes,
+        old_size - fs::metadata(&path).unwrap().len()
+    );
+    assert!(report.reclaimed_bytes > 0);
+
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    assert!(!base.is_locked().unwrap());
+    assert_eq!(base.active_messages(), 2);
+    assert_eq!(base.lowest_message_number(), 3);
+    assert_eq!(base.highest_message_number(), 4);
+    assert_eq!(base.iter().count(), 2);
+    assert!(base.read_message(1).is_err());
+
+    let msg = base.read_message(3).unwrap();
+    assert_eq!(msg.header.msg_number, 3);
+    assert_eq!(msg.header.subj_field, expected.header.subj_field);
+    assert_eq!(msg.text, expected.text);
+
+    let idx = base.read_index().unwrap();
+    assert_eq!(idx.len(), 4);
+    assert_eq!(idx[0].num, 0);
+    assert_eq!(idx[2].offset, 128);
+    assert_eq!(
+        fs::metadata(path.with_extension("ndx")).unwrap().len(),
+        16384
+    );
+    assert_eq!(
+        base.read_old_index_records().unwrap(),
+        vec![(3, 128), (4, idx[3].offset)]
+    );
+    assert!(!path.with_extension("idx.tmp").exists());
+}
+
+#[test]
+fn test_pack_damaged_index() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    base.delete_message(1).unwrap();
+    // messages 3 and 4 lost their index records
+    let idx = fs::read(path.with_extension("idx")).unwrap();
+    fs::write(path.with_extension("idx"), &idx[..2 * 64]).unwrap();
+
+    let report = base.pack().unwrap();
+    assert_eq!(report.removed_messages, vec![1]);
+    let base = PCBoardMessageBase::open(&path).unwrap();
+    assert_eq!(base.active_messages(), 3);
+    assert_eq!(base.read_index().unwrap().len(), 4);
+    assert_eq!(base.read_message(4).unwrap().header.msg_number, 4);
+}
+
+#[test]
+fn test_pack_damaged_message_file() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    base.delete_message(1).unwrap();
+    let offset = base.read_index().unwrap()[2].offset as usize;
+    let mut data = fs::read(&path).unwrap();
+    data[offset..offset + 128].fill(b'X');
+    fs::write(&path, &data).unwrap();
+
+    assert!(base.pack().is_err());
+    assert_eq!(fs::read(&path).unwrap()[128..], data[128..]);
+    assert!(!base.is_locked().unwrap());
+    assert!(!path.with_extension("tmp").exists());
+    assert!(!path.with_extension("idx.tmp").exists());
+    assert!(!path.with_extension("ndx.tmp").exists());
+}
+
+#[test]
+fn test_reindex() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let expected = PCBoardMessageBase::open(&path)
+        .unwrap()
+        .read_index()
+        .unwrap();
+    fs::write(path.with_extension("idx"), [0xFF; 100]).unwrap();
+    fs::remove_file(path.with_extension("ndx")).unwrap();
+
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let report = base.reindex(true).unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.messages, 4);
+    assert_eq!(report.active_messages, 4);
+    assert!(!base.is_locked().unwrap());
+
+    let idx = base.read_index().unwrap();
+    assert_eq!(idx.len(), expected.len());
+    for (idx, expected) in idx.iter().zip(&expected) {
+        assert_eq!(idx.offset, expected.offset);
+        assert_eq!(idx.num, expected.num);
+        assert_eq!(idx.to, expected.to);
+        assert_eq!(idx.from, expected.from);
+        assert_eq!(idx.status, expected.status);
+        assert_eq!(idx.date, expected.date);
+    }
+    assert_eq!(
+        fs::read(path.with_extension("ndx")).unwrap(),
+        fs::read("data/pcboard/test.ndx").unwrap()
+    );
+    assert_eq!(base.read_message(3).unwrap().header.msg_number, 3);
+}
+
+#[test]
+fn test_reindex_damaged_base() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let data = fs::read(&path).unwrap();
+    let idx = PCBoardMessageBase::open(&path)
+        .unwrap()
+        .read_index()
+        .unwrap();
+    // swap message 1 & 2, insert a garbage block and cut the last message
+    let (first, second, last) = (
+        idx[0].offset as usize,
+        idx[1].offset as usize,
+        idx[3].offset as usize,
+    );
+    let mut damaged = data[..first].to_vec();
+    damaged.extend(&data[second..idx[2].offset as usize]);
+    damaged.extend(&data[first..second]);
+    damaged.extend([0; 128]);
+    damaged.extend(&data[idx[2].offset as usize..last + 128 + 10]);
+    fs::write(&path, &damaged).unwrap();
+
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let report = base.reindex(false).unwrap();
+    let second_offset = (first + idx[2].offset as usize - second) as u64;
+    let garbage_offset = second_offset + (second - first) as u64;
+    assert_eq!(
+        report.issues,
+        vec![
+            reindex::PCBoardIndexIssue::PartialBlock(damaged.len() as u64),
+            reindex::PCBoardIndexIssue::MessageNumberOutOfOrder {
+                offset: second_offset,
+                message_number: 1,
+                previous_number: 2,
+            },
+            reindex::PCBoardIndexIssue::InvalidHeader {
+                offset: garbage_offset
+            },
+            reindex::PCBoardIndexIssue::TruncatedMessage {
+                offset: garbage_offset + 128 + (last - idx[2].offset as usize) as u64,
+                message_number: 4,
+                // the block count follows status, number & reply number
+                num_blocks: data[last + 9],
+            },
+            reindex::PCBoardIndexIssue::ActiveMessagesMismatch {
+                header: 4,
+                actual: 3
+            },
+        ]
+    );
+    assert_eq!(report.messages, 3);
+    assert_eq!(base.active_messages(), 3);
+    assert_eq!(base.read_message(1).unwrap().header.msg_number, 1);
+    assert_eq!(base.read_message(2).unwrap().header.msg_number, 2);
+    assert!(base.read_message(4).is_err());
+}
+
+#[test]
+fn test_reindex_unknown_extended_header() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let msg = PCBoardMessage::new()
+        .with_from(BString::from("SYSOP"))
+        .with_to(BString::from("ALL"))
+        .with_subject(BString::from("A subject that is too long for the header"))
+        .with_date_time(
+            NaiveDate::from_ymd_opt(2024, 4, 5)
+                .unwrap()
+                .and_hms_opt(22, 20, 0)
+                .unwrap(),
+        )
+        .with_text(BString::from("text\n"));
+    assert_eq!(base.write_message(&msg).unwrap(), 5);
+
+    // rename the SUBJECT extended header to a function this crate doesn't know
+    let mut data = fs::read(&path).unwrap();
+    let offset = base.read_index().unwrap()[4].offset as usize;
+    let pos = data[offset..].find(b"SUBJECT").unwrap() + offset;
+    data[pos..pos + 7].copy_from_slice(b"UNKNOWN");
+    fs::write(&path, &data).unwrap();
+    assert!(base.read_message(5).is_err());
+
+    let report = base.reindex(true).unwrap();
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.messages, 5);
+    assert_eq!(base.read_index().unwrap()[4].offset as usize, offset);
+}
+
+#[test]
+fn test_reindex_garbage_number() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = copy_test_base(tmpdir.path());
+    let mut data = fs::read(&path).unwrap();
+    let first = PCBoardMessageBase::open(&path)
+        .unwrap()
+        .read_index()
+        .unwrap()[0]
+        .offset as usize;
+
+    // a header block with text bytes as message number
+    let garbage_offset = data.len() as u64;
+    let mut block = data[first..first + 128].to_vec();
+    block[1..5].copy_from_slice(&[0xE3; 4]);
+    block[9] = 1;
+    data.extend(block);
+    fs::write(&path, &data).unwrap();
+
+    let mut base = PCBoardMessageBase::open(&path).unwrap();
+    let report = base.reindex(true).unwrap();
+    assert_eq!(
+        report.issues,
+        vec![reindex::PCBoardIndexIssue::InvalidHeader {
+            offset: garbage_offset
+        }]
+    );
+    assert_eq!(report.messages, 4);
+    assert_eq!(base.read_index().unwrap().len(), 4);
+}
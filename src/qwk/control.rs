@@ -2,6 +2,7 @@ use bstr::{BString, ByteSlice};
 
 use super::QwkError;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Conference {
     /// Conference number
     /// Note: Qwk limits conferences to u16
@@ -10,6 +11,7 @@ pub struct Conference {
     pub name: bstr::BString,
 }
 
+#[derive(Debug, Default, Clone)]
 pub struct ControlDat {
     /// BBS name
     pub bbs_name: bstr::BString,
@@ -80,8 +82,9 @@ impl ControlDat {
 
         let num_conferences = lines.next().unwrap_or_default();
         let mut conferences = Vec::new();
+        // The line contains the total number of conferences minus 1
         if let Ok(num) = num_conferences.to_str()?.parse::<u16>() {
-            for _ in 0..num {
+            for _ in 0..=num {
                 let number_txt = lines.next().unwrap_or_default();
                 let name = BString::from(lines.next().unwrap_or_default());
 
@@ -174,7 +177,12 @@ impl ControlDat {
         s.extend(EOL);
         s.extend(self.message_count.to_string().as_bytes());
         s.extend(EOL);
-        s.extend((self.conferences.len() as u16).to_string().as_bytes());
+        s.extend(
+            (self.conferences.len() as u16)
+                .saturating_sub(1)
+                .to_string()
+                .as_bytes(),
+        );
         s.extend(EOL);
         for conference in &self.conferences {
             s.extend(conference.number.to_string().as_bytes());
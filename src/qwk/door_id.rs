@@ -0,0 +1,178 @@
+//! DOOR.ID, see doc/qwk/qwk11/DOORID.TXT and the QWKE additions in doc/qwk/qwke.txt
+use bstr::{BString, ByteSlice};
+
+use super::split_word;
+
+/// Common CONTROLTYPE values
+pub mod control_types {
+    pub const ADD: &str = "ADD";
+    pub const DROP: &str = "DROP";
+    pub const REQUEST: &str = "REQUEST";
+    pub const RESET: &str = "RESET";
+    /// QWKE: max keywords the door can handle, takes a number
+    pub const MAX_KEYWORDS: &str = "MAXKEYWORDS";
+    /// QWKE: max filters the door can handle, takes a number
+    pub const MAX_FILTERS: &str = "MAXFILTERS";
+    /// QWKE: max twits the door can handle, takes a number
+    pub const MAX_TWITS: &str = "MAXTWITS";
+    /// QWKE: file attachments allowed
+    pub const ALLOW_ATTACH: &str = "ALLOWATTACH";
+    /// QWKE: file uploads allowed
+    pub const ALLOW_FILES: &str = "ALLOWFILES";
+    /// QWKE: file requests allowed
+    pub const ALLOW_REQUESTS: &str = "ALLOWREQUESTS";
+    /// QWKE: max number of daily file requests, takes a number
+    pub const MAX_REQUESTS: &str = "MAXREQUESTS";
+}
+
+const EOL: &[u8; 1] = b"\n";
+
+/// Capabilities of the door that created a QWK packet.
+///
+/// # Remarks
+/// None of the lines are required, empty fields aren't written.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DoorId {
+    /// DOOR - name of the door that created the packet
+    pub door: BString,
+    /// VERSION - version of the door
+    pub version: BString,
+    /// SYSTEM - BBS system type and version
+    pub system: BString,
+    /// CONTROLNAME - name control messages are sent to
+    pub control_name: BString,
+    /// CONTROLTYPE - accepted control commands incl. arguments (e.g. "MAXKEYWORDS 10")
+    pub control_types: Vec<BString>,
+    /// RECEIPT - return receipts are supported ("RRR" subject prefix)
+    pub receipt: bool,
+    /// MIXEDCASE - mixed case names and subjects are allowed
+    pub mixed_case: bool,
+    /// FIDOTAG - Fidonet compliant tag-lines are used
+    pub fido_tag: bool,
+}
+
+impl DoorId {
+    pub const FILE_NAME: &'static str = "door.id";
+
+    pub fn new(door: &str, version: &str) -> Self {
+        Self {
+            door: door.into(),
+            version: version.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_system(mut self, system: &str) -> Self {
+        self.system = system.into();
+        self
+    }
+
+    pub fn with_control_name(mut self, control_name: &str) -> Self {
+        self.control_name = control_name.into();
+        self
+    }
+
+    /// Adds a CONTROLTYPE, see `control_types`
+    pub fn with_control_type(mut self, control_type: &str) -> Self {
+        self.control_types.push(control_type.into());
+        self
+    }
+
+    pub fn with_receipt(mut self, receipt: bool) -> Self {
+        self.receipt = receipt;
+        self
+    }
+
+    pub fn with_mixed_case(mut self, mixed_case: bool) -> Self {
+        self.mixed_case = mixed_case;
+        self
+    }
+
+    pub fn with_fido_tag(mut self, fido_tag: bool) -> Self {
+        self.fido_tag = fido_tag;
+        self
+    }
+
+    /// Checks if the door accepts a control command, case insensitive.
+    pub fn accepts(&self, control_type: &str) -> bool {
+        self.find_control_type(control_type).is_some()
+    }
+
+    /// Number argument of a control type (e.g. MAXKEYWORDS 10)
+    pub fn get_control_value(&self, control_type: &str) -> Option<u32> {
+        self.find_control_type(control_type)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Returns the arguments of a control type.
+    fn find_control_type(&self, control_type: &str) -> Option<&[u8]> {
+        self.control_types.iter().find_map(|line| {
+            let (name, arguments) = split_word(line);
+            name.eq_ignore_ascii_case(control_type.as_bytes())
+                .then_some(arguments)
+        })
+    }
+
+    /// Parses DOOR.ID, unknown lines are ignored.
+    pub fn read(data: &[u8]) -> Self {
+        let mut res = Self::default();
+        for line in data.lines() {
+            let (key, value) = match line.find_byte(b'=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => (line.trim(), &[][..]),
+            };
+            let is_set = value.is_empty() || value.eq_ignore_ascii_case(b"YES");
+            match key.to_ascii_uppercase().as_slice() {
+                b"DOOR" => res.door = value.into(),
+                b"VERSION" => res.version = value.into(),
+                b"SYSTEM" => res.system = value.into(),
+                b"CONTROLNAME" => res.control_name = value.into(),
+                b"CONTROLTYPE" => res.control_types.push(value.into()),
+                b"RECEIPT" => res.receipt = is_set,
+                b"MIXEDCASE" => res.mixed_case = is_set,
+                b"FIDOTAG" => res.fido_tag = is_set,
+                _ => {}
+            }
+        }
+        res
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut s = Vec::new();
+        for (key, value) in [
+            ("DOOR", &self.door),
+            ("VERSION", &self.version),
+            ("SYSTEM", &self.system),
+            ("CONTROLNAME", &self.control_name),
+        ] {
+            if !value.is_empty() {
+                write_line(&mut s, key, value);
+            }
+        }
+        for control_type in &self.control_types {
+            write_line(&mut s, "CONTROLTYPE", control_type);
+        }
+        if self.receipt {
+            s.extend(b"RECEIPT");
+            s.extend(EOL);
+        }
+        if self.mixed_case {
+            write_line(&mut s, "MIXEDCASE", b"YES");
+        }
+        if self.fido_tag {
+            write_line(&mut s, "FIDOTAG", b"YES");
+        }
+        s
+    }
+}
+
+fn write_line(s: &mut Vec<u8>, key: &str, value: &[u8]) {
+    s.extend(key.as_bytes());
+    s.extend(b" = ");
+    s.extend(value);
+    s.extend(EOL);
+}
@@ -0,0 +1,97 @@
+use bstr::ByteSlice;
+
+use crate::message_base::{flags, Message, MessageBase, MessageBaseError, MessageBaseFormat};
+
+use super::{
+    qwk_message::{QWKMessage, MSG_ACTIVE, MSG_INACTIVE},
+    QwkMessageBase,
+};
+
+impl MessageBase for QwkMessageBase {
+    fn format(&self) -> MessageBaseFormat {
+        MessageBaseFormat::Qwk
+    }
+
+    /// QWK packets don't store the number of active messages, so all messages are scanned.
+    fn active_messages(&self) -> u32 {
+        self.iter()
+            .flatten()
+            .filter(|msg| !msg.is_deleted())
+            .count() as u32
+    }
+
+    /// QWK message numbers are only unique within a conference, the first match is returned.
+    fn get_message(&self, number: u32) -> crate::Result<Message> {
+        for msg in self.iter() {
+            let msg = msg?;
+            if msg.msg_number == number {
+                return Ok(msg.into());
+            }
+        }
+        Err(MessageBaseError::MessageNotFound(number).into())
+    }
+
+    fn messages(&self) -> Box<dyn Iterator<Item = crate::Result<Message>> + '_> {
+        Box::new(self.iter().map(|msg| msg.map(Message::from)))
+    }
+}
+
+impl From<QWKMessage> for Message {
+    fn from(msg: QWKMessage) -> Self {
+        let mut flags = 0;
+        if b"*+~`".contains(&msg.status) {
+            flags |= flags::PRIVATE;
+        }
+        if b"-+`^#".contains(&msg.status) {
+            flags |= flags::READ;
+        }
+        if msg.is_deleted() {
+            flags |= flags::DELETED;
+        }
+        Message {
+            number: msg.msg_number,
+            conference: msg.conference_number,
+            date_written: msg.date_time(),
+            flags,
+            reply_to: msg.ref_msg_number,
+            from: msg.from,
+            to: msg.to,
+            subject: msg.subj,
+            password: msg.password,
+            // the last block is padded with spaces
+            text: msg.text.trim_end_with(|c| c == ' ' || c == '\0').into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl QWKMessage {
+    /// Creates a QWK message for the given conference from a message of any format.
+    pub fn from_message(msg: &Message, conference: u16) -> Self {
+        let status = match (msg.is_private(), msg.has_flag(flags::READ)) {
+            (true, true) => b'+',
+            (true, false) => b'*',
+            (false, true) => b'-',
+            (false, false) => b' ',
+        };
+        QWKMessage {
+            status,
+            msg_number: msg.number,
+            date_time: msg.date_written.format("%m-%d-%y%H:%M").to_string().into(),
+            to: msg.to.clone(),
+            from: msg.from.clone(),
+            subj: msg.subject.clone(),
+            password: msg.password.clone(),
+            ref_msg_number: msg.reply_to,
+            active_flag: if msg.is_deleted() {
+                MSG_INACTIVE
+            } else {
+                MSG_ACTIVE
+            },
+            conference_number: conference,
+            logical_message_number: 0,
+            net_tag: b' ',
+            text: msg.text.clone(),
+        }
+    }
+}
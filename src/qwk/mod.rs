@@ -1,21 +1,30 @@
 use std::{
     fs::{self, File},
-    io::{BufReader, Seek},
-    path::{Path, PathBuf},
+    io::{Read, Seek},
+    path::Path,
 };
 
-use bstr::BString;
+use bstr::{BString, ByteSlice};
 use thiserror::Error;
 
 use crate::util::basic_real::basicreal_to_u32;
 
 use self::{
     control::{Conference, ControlDat},
+    door_id::DoorId,
     qwk_message::QWKMessage,
+    qwke::ToReaderExt,
+    storage::{PacketStorage, ReadSeek},
 };
 
 pub mod control;
+pub mod door_id;
+mod message_base;
+pub mod packet_builder;
 pub mod qwk_message;
+pub mod qwke;
+pub mod rep;
+mod storage;
 
 #[cfg(test)]
 mod tests;
@@ -33,14 +42,35 @@ pub enum QwkError {
 
     #[error("Message number in mail header invalid.")]
     InvalidMessageNumber,
+
+    #[error("Packet file {0} not found")]
+    FileNotFound(String),
+
+    #[error("Invalid QWKE extension line ({0})")]
+    InvalidExtensionLine(BString),
+
+    #[error("Packet file {0} exceeds the size limit")]
+    FileTooLarge(String),
+
+    #[error("Packet exceeds the size limit of {0} bytes")]
+    PacketTooLarge(u64),
+
+    #[error("Reply packet contains more than one .MSG file ({0})")]
+    MultipleMessageFiles(String),
+}
+
+/// Mail of a conference read by `QwkMessageBase::read_conference`
+#[derive(Debug, Default, Clone)]
+pub struct ConferenceMail {
+    pub messages: Vec<QWKMessage>,
+    /// The NNN.NDX file uses 0 based record numbers and needed the block offset fixup
+    pub index_fixup: bool,
 }
 
 pub struct QwkMessageBase {
-    path: PathBuf,
+    storage: PacketStorage,
     control_dat: ControlDat,
     is_extended: bool,
-
-    pub index_offset_bug: bool,
 }
 
 impl QwkMessageBase {
@@ -95,17 +125,54 @@ impl QwkMessageBase {
     /// opens an existing message base with base path (without any extension)
     /// extended flag for setting if it's a qwke base
     /// should be safe to always have this enabled.
+    ///
+    /// # Remarks
+    /// The path is either an extracted packet directory or a packet archive (BBSID.QWK).
     pub fn open<P: AsRef<Path>>(path: P, is_extended: bool) -> crate::Result<Self> {
-        let control_dat_path = fs::read(path.as_ref().join("control.dat"))?;
-        let control_dat = ControlDat::read(&control_dat_path)?;
+        let path = path.as_ref();
+        if path.is_file() {
+            return Self::open_archive(File::open(path)?, is_extended);
+        }
+        Self::open_storage(PacketStorage::Directory(path.to_path_buf()), is_extended)
+    }
+
+    /// opens a packet from a ZIP archive, the archive is read into memory.
+    pub fn open_archive<R: Read + Seek>(reader: R, is_extended: bool) -> crate::Result<Self> {
+        Self::open_storage(PacketStorage::open_archive(reader)?, is_extended)
+    }
+
+    fn open_storage(storage: PacketStorage, is_extended: bool) -> crate::Result<Self> {
+        let control_dat = ControlDat::read(&storage.read("control.dat")?)?;
         Ok(Self {
-            path: path.as_ref().to_path_buf(),
+            storage,
             is_extended,
             control_dat,
-            index_offset_bug: false,
         })
     }
 
+    /// Reads the DOOR.ID file, `None` if the packet doesn't contain one.
+    pub fn read_door_id(&self) -> crate::Result<Option<DoorId>> {
+        if !self.storage.exists(DoorId::FILE_NAME) {
+            return Ok(None);
+        }
+        Ok(Some(DoorId::read(&self.storage.read(DoorId::FILE_NAME)?)))
+    }
+
+    /// Reads the QWKE TOREADER.EXT file, `None` if the packet doesn't contain one.
+    pub fn read_toreader_ext(&self) -> crate::Result<Option<ToReaderExt>> {
+        if !self.storage.exists(ToReaderExt::FILE_NAME) {
+            return Ok(None);
+        }
+        Ok(Some(ToReaderExt::read(
+            &self.storage.read(ToReaderExt::FILE_NAME)?,
+        )?))
+    }
+
+    /// Reads a file of the packet (e.g. the welcome screen), names are case insensitive.
+    pub fn read_file(&self, name: &str) -> crate::Result<Vec<u8>> {
+        self.storage.read(name)
+    }
+
     pub fn get_conferences(&self) -> &[Conference] {
         &self.control_dat.conferences
     }
@@ -124,49 +191,144 @@ impl QwkMessageBase {
         Ok(res)
     }
 
+    /// Reads the mail of a conference using its NNN.NDX file.
+    /// Conferences without mail have no index file.
     pub fn read_conference_mail(&self, conference: u16) -> crate::Result<Vec<QWKMessage>> {
-        let file_name = format!("{:03}.ndx", conference);
-        let index = Self::read_qwk_index(self.path.join(file_name))?;
-        let mut res = Vec::with_capacity(index.len());
+        Ok(self.read_conference(conference)?.messages)
+    }
 
-        let msg_file_name = self.path.join("messages.dat");
-        let mut reader = BufReader::new(File::open(msg_file_name)?);
-        for block in index {
-            // First block is the header block, so we need to subtract 1
-            // Unfortunately not all index files require that.
-            let mut block = block - 1;
+    /// Reads the mail of a conference using its NNN.NDX file and reports
+    /// whether the index needed the block offset fixup.
+    pub fn read_conference(&self, conference: u16) -> crate::Result<ConferenceMail> {
+        let Some(index) = self.read_conference_index(conference)? else {
+            return Ok(ConferenceMail::default());
+        };
+        let mut res = Vec::with_capacity(index.len());
 
-            // easier to remove that way - hopefully there is a way to detect that which I overlooked.
-            if self.index_offset_bug {
-                block += 1;
+        let mut reader = self.storage.open("messages.dat")?;
+        let offset = Self::detect_index_offset(&mut reader, &index)?;
+        if offset == 1 {
+            log::warn!("{:03}.ndx uses 0 based record numbers", conference);
+        }
+        let size = reader.seek(std::io::SeekFrom::End(0))?;
+        let mut header = [0; QWKMessage::HEADER_SIZE];
+        for record in index {
+            let pos = Self::header_position(record, offset)
+                .filter(|pos| pos + QWKMessage::HEADER_SIZE as u64 <= size);
+            let Some(pos) = pos else {
+                log::warn!("{:03}.ndx: invalid record {}", conference, record);
+                continue;
+            };
+            reader.seek(std::io::SeekFrom::Start(pos))?;
+            reader.read_exact(&mut header)?;
+            if !QWKMessage::is_valid_header(&header) {
+                log::warn!(
+                    "{:03}.ndx: record {} isn't a message header",
+                    conference,
+                    record
+                );
+                continue;
             }
-
-            reader.seek(std::io::SeekFrom::Start(block as u64 * 128))?;
+            reader.seek(std::io::SeekFrom::Start(pos))?;
             let mail = QWKMessage::read(&mut reader, self.is_extended)?;
             res.push(mail);
         }
 
+        Ok(ConferenceMail {
+            messages: res,
+            index_fixup: offset == 1,
+        })
+    }
+
+    /// Conferences with NDX files that need the block offset fixup.
+    pub fn get_index_fixups(&self) -> crate::Result<Vec<u16>> {
+        let mut res = Vec::new();
+        let mut reader = self.storage.open("messages.dat")?;
+        for conference in self.get_conferences() {
+            let Some(index) = self.read_conference_index(conference.number)? else {
+                continue;
+            };
+            if Self::detect_index_offset(&mut reader, &index)? == 1 {
+                res.push(conference.number);
+            }
+        }
         Ok(res)
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = crate::Result<QWKMessage>> {
-        let idx_file_name = self.path.join("messages.dat");
-        let mut f = File::open(idx_file_name).unwrap();
-        let size = f.metadata().unwrap().len();
-        f.seek(std::io::SeekFrom::Start(128)).unwrap();
-        QWKMessageIter {
-            reader: BufReader::new(f),
-            size,
+    fn read_conference_index(&self, conference: u16) -> crate::Result<Option<Vec<u32>>> {
+        let index_name = format!("{:03}.ndx", conference);
+        if !self.storage.exists(&index_name) {
+            return Ok(None);
+        }
+        Ok(Some(Self::convert_qwk_index(
+            &self.storage.read(&index_name)?,
+        )?))
+    }
+
+    /// Detects how the record numbers of an NDX file need to be adjusted.
+    ///
+    /// # Remarks
+    /// Record numbers should be 1 based and count the packet header block,
+    /// so the first message is record 2. Some doors write 0 based numbers instead.
+    /// Returns 1 if all records point to a message header only after adding 1, otherwise 0.
+    fn detect_index_offset(reader: &mut dyn ReadSeek, index: &[u32]) -> crate::Result<u32> {
+        let size = reader.seek(std::io::SeekFrom::End(0))?;
+        let mut is_valid = |offset: u32| -> crate::Result<bool> {
+            let mut header = [0; QWKMessage::HEADER_SIZE];
+            for record in index {
+                let Some(pos) = Self::header_position(*record, offset) else {
+                    return Ok(false);
+                };
+                if pos + QWKMessage::HEADER_SIZE as u64 > size {
+                    return Ok(false);
+                }
+                reader.seek(std::io::SeekFrom::Start(pos))?;
+                reader.read_exact(&mut header)?;
+                if !QWKMessage::is_valid_header(&header) {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        if !is_valid(0)? && is_valid(1)? {
+            return Ok(1);
         }
+        Ok(0)
+    }
+
+    /// Position of the message header an NDX record points to.
+    /// `None` if the record doesn't point behind the packet header block.
+    fn header_position(record: u32, offset: u32) -> Option<u64> {
+        let block = record.checked_add(offset)?.checked_sub(1)?;
+        if block == 0 {
+            return None;
+        }
+        Some(block as u64 * QWKMessage::HEADER_SIZE as u64)
+    }
+
+    /// Iterates over all messages of messages.dat.
+    /// If messages.dat can't be opened the iterator yields a single error.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = crate::Result<QWKMessage>> + '_> {
+        match self.open_messages() {
+            Ok(iter) => Box::new(iter),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+
+    fn open_messages(&self) -> crate::Result<QWKMessageIter<'_>> {
+        let mut reader = self.storage.open("messages.dat")?;
+        let size = reader.seek(std::io::SeekFrom::End(0))?;
+        reader.seek(std::io::SeekFrom::Start(128))?;
+        Ok(QWKMessageIter { reader, size })
     }
 }
 
-struct QWKMessageIter {
-    reader: BufReader<File>,
+struct QWKMessageIter<'a> {
+    reader: Box<dyn ReadSeek + 'a>,
     size: u64,
 }
 
-impl Iterator for QWKMessageIter {
+impl Iterator for QWKMessageIter<'_> {
     type Item = crate::Result<QWKMessage>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -180,3 +342,12 @@ impl Iterator for QWKMessageIter {
         }
     }
 }
+
+/// Splits off the first word, the rest is returned without leading white space.
+pub(crate) fn split_word(data: &[u8]) -> (&[u8], &[u8]) {
+    let data = data.trim();
+    match data.find_byteset(b" \t") {
+        Some(i) => (&data[..i], data[i..].trim_start()),
+        None => (data, &[]),
+    }
+}
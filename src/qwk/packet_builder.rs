@@ -0,0 +1,199 @@
+use std::{
+    collections::BTreeMap,
+    io::{Seek, Write},
+    path::Path,
+};
+
+use bstr::BString;
+
+use crate::{
+    message_base::{Message, MessageBase},
+    util::basic_real::u32_to_basicreal,
+};
+
+use super::{
+    control::ControlDat,
+    door_id::DoorId,
+    qwk_message::QWKMessage,
+    qwke::ToReaderExt,
+    storage::{write_archive, write_directory},
+};
+
+const DEFAULT_PRODUCER: &str = "Produced by jamjam";
+
+/// Writes QWK packets (door side).
+///
+/// # Remarks
+/// The packet is written as directory or ZIP archive in the layout `QwkMessageBase::open` reads:
+/// control.dat, messages.dat, one NNN.ndx file per conference with mail, personal.ndx
+/// for mail to the packet user and the optional door.id, welcome, news and goodbye files.
+pub struct QwkPacketBuilder {
+    control_dat: ControlDat,
+    producer: BString,
+    is_extended: bool,
+    messages: Vec<QWKMessage>,
+    door_id: Option<DoorId>,
+    toreader_ext: Option<ToReaderExt>,
+    welcome_screen: Option<BString>,
+    news_screen: Option<BString>,
+    goodbye_screen: Option<BString>,
+}
+
+impl QwkPacketBuilder {
+    /// The message count of `control_dat` is set when the packet is written.
+    pub fn new(control_dat: ControlDat) -> Self {
+        Self {
+            control_dat,
+            producer: BString::from(DEFAULT_PRODUCER),
+            is_extended: false,
+            messages: Vec::new(),
+            door_id: None,
+            toreader_ext: None,
+            welcome_screen: None,
+            news_screen: None,
+            goodbye_screen: None,
+        }
+    }
+
+    /// Text of the packet header block, should start with "Produced by ".
+    pub fn with_producer(mut self, producer: BString) -> Self {
+        self.producer = producer;
+        self
+    }
+
+    /// Writes long To/From/Subject fields as QWKE kludge lines.
+    pub fn with_extended(mut self, is_extended: bool) -> Self {
+        self.is_extended = is_extended;
+        self
+    }
+
+    pub fn with_door_id(mut self, door_id: DoorId) -> Self {
+        self.door_id = Some(door_id);
+        self
+    }
+
+    /// Adds a QWKE TOREADER.EXT file.
+    pub fn with_toreader_ext(mut self, toreader_ext: ToReaderExt) -> Self {
+        self.toreader_ext = Some(toreader_ext);
+        self
+    }
+
+    /// Content of the welcome screen, the file name is taken from control.dat.
+    pub fn with_welcome_screen(mut self, content: BString) -> Self {
+        self.welcome_screen = Some(content);
+        self
+    }
+
+    /// Content of the news file, the file name is taken from control.dat.
+    pub fn with_news_screen(mut self, content: BString) -> Self {
+        self.news_screen = Some(content);
+        self
+    }
+
+    /// Content of the goodbye screen, the file name is taken from control.dat.
+    pub fn with_goodbye_screen(mut self, content: BString) -> Self {
+        self.goodbye_screen = Some(content);
+        self
+    }
+
+    /// Adds a message, the logical message number is assigned when the packet is written.
+    pub fn add_message(&mut self, msg: QWKMessage) {
+        self.messages.push(msg);
+    }
+
+    /// Adds all active messages of a message base to a conference.
+    /// Returns the number of added messages.
+    pub fn add_message_base(
+        &mut self,
+        conference: u16,
+        base: &dyn MessageBase,
+    ) -> crate::Result<u32> {
+        let mut added = 0;
+        for msg in base.messages() {
+            let msg: Message = msg?;
+            if msg.is_deleted() {
+                continue;
+            }
+            self.add_message(QWKMessage::from_message(&msg, conference));
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Writes the packet files to a directory.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        write_directory(path.as_ref(), &self.packet_files()?)
+    }
+
+    /// Writes the packet as ZIP archive (BBSID.QWK).
+    pub fn write_archive<W: Write + Seek>(&self, writer: W) -> crate::Result<W> {
+        write_archive(writer, &self.packet_files()?)
+    }
+
+    fn packet_files(&self) -> crate::Result<Vec<(String, Vec<u8>)>> {
+        let mut files = Vec::new();
+        let mut conference_index: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+        let mut personal_index = Vec::new();
+        let mut messages_dat = self.producer.to_vec();
+        messages_dat.resize(QWKMessage::HEADER_SIZE, b' ');
+
+        // record numbers are 1 based, the packet header is record 1
+        let mut record = 2;
+        for (i, msg) in self.messages.iter().enumerate() {
+            let mut msg = msg.clone();
+            msg.logical_message_number = (i + 1) as u16;
+            let start = messages_dat.len();
+            msg.write(&mut messages_dat, self.is_extended)?;
+
+            let mut ndx = u32_to_basicreal(record).to_le_bytes().to_vec();
+            ndx.push(msg.conference_number as u8);
+            if msg
+                .to
+                .eq_ignore_ascii_case(&self.control_dat.qmail_user_name)
+            {
+                personal_index.extend_from_slice(&ndx);
+            }
+            conference_index
+                .entry(msg.conference_number)
+                .or_default()
+                .extend_from_slice(&ndx);
+            record += ((messages_dat.len() - start) / QWKMessage::HEADER_SIZE) as u32;
+        }
+        files.push(("messages.dat".to_string(), messages_dat));
+
+        for (conference, ndx) in conference_index {
+            files.push((format!("{:03}.ndx", conference), ndx));
+        }
+        if !personal_index.is_empty() {
+            files.push(("personal.ndx".to_string(), personal_index));
+        }
+
+        let mut control_dat = self.control_dat.clone();
+        control_dat.message_count = self.messages.len() as u32;
+        files.push(("control.dat".to_string(), control_dat.write()));
+
+        if let Some(door_id) = &self.door_id {
+            files.push((DoorId::FILE_NAME.to_string(), door_id.write()));
+        }
+        if let Some(toreader_ext) = &self.toreader_ext {
+            files.push((ToReaderExt::FILE_NAME.to_string(), toreader_ext.write()));
+        }
+        let screens = [
+            (&control_dat.welcome_screen, &self.welcome_screen),
+            (&control_dat.news_screen, &self.news_screen),
+            (&control_dat.logoff_screen, &self.goodbye_screen),
+        ];
+        for (file_name, content) in screens {
+            if let Some(content) = content {
+                if !file_name.is_empty() {
+                    files.push((file_name.to_string(), content.to_vec()));
+                }
+            }
+        }
+        Ok(files)
+    }
+}
@@ -1,10 +1,7 @@
 use bstr::{BString, ByteSlice};
 
 use crate::{pcboard::PCB_TXT_EOL_PTR, qwk::QwkError};
-use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Read, Write},
-};
+use std::io::{Read, Write};
 
 pub enum MessageType {
     Public,
@@ -98,7 +95,27 @@ impl QWKMessage {
         self.active_flag != MSG_ACTIVE
     }
 
-    pub fn read(file: &mut BufReader<File>, is_extended: bool) -> crate::Result<Self> {
+    /// Checks if a 128-byte block looks like a message header.
+    ///
+    /// # Remarks
+    /// Checks the status byte, the ascii number fields and the active flag.
+    pub fn is_valid_header(data: &[u8]) -> bool {
+        if data.len() < Self::HEADER_SIZE || !b" -*+~`%^!#$".contains(&data[0]) {
+            return false;
+        }
+        let is_number = |field: &[u8]| {
+            let field = field.trim_with(|c| c == ' ' || c == '\0');
+            field.iter().all(u8::is_ascii_digit)
+        };
+        let blocks = parse_qwk_number(&data[116..122]).unwrap_or_default();
+        is_number(&data[1..8])
+            && is_number(&data[108..116])
+            && is_number(&data[116..122])
+            && blocks >= 1
+            && (data[122] == MSG_ACTIVE || data[122] == MSG_INACTIVE)
+    }
+
+    pub fn read<R: Read>(file: &mut R, is_extended: bool) -> crate::Result<Self> {
         let data = &mut [0; Self::HEADER_SIZE];
         file.read_exact(data)?;
         let mut data = &data[..];
@@ -132,6 +149,7 @@ impl QWKMessage {
         let mut subj_field = subj_field;
 
         if is_extended {
+            let mut has_kludges = false;
             loop {
                 let kludge = get_kludge(&text);
                 if kludge == 0 {
@@ -144,7 +162,13 @@ impl QWKMessage {
                     3 => subj_field = line[9..].into(), // "Subject: "
                     _ => {}
                 }
-                text = text[line.len() + 1..].into();
+                // the last line may end without a line break
+                text = text.get(line.len() + 1..).unwrap_or_default().into();
+                has_kludges = true;
+            }
+            // skip the blank line that separates the kludges from the text
+            if has_kludges && text.starts_with(b"\n") {
+                text = text[1..].into();
             }
         }
 
@@ -165,64 +189,66 @@ impl QWKMessage {
         })
     }
 
-    pub fn write(&self, file: &mut BufWriter<File>, is_extended: bool) -> crate::Result<()> {
-        file.write_all(&[self.status])?;
-        file.write_all(format!("{:0}", self.msg_number).as_bytes())?;
-        file.write_all(&self.date_time)?;
-
-        let mut to_vec = self.to.to_vec();
-        to_vec.resize(25, b' ');
-        file.write_all(&to_vec)?;
-
-        let mut from_vec = self.from.to_vec();
-        from_vec.resize(25, b' ');
-        file.write_all(&from_vec)?;
-
-        let mut subj_vec = self.subj.to_vec();
-        subj_vec.resize(25, b' ');
-        file.write_all(&subj_vec)?;
-
-        let mut password_vec = self.password.to_vec();
-        password_vec.resize(12, b' ');
-        file.write_all(&password_vec)?;
-        file.write_all(format!("{:8}", self.ref_msg_number).as_bytes())?;
-        file.write_all(format!("{:6}", self.text.len() / 128 + 1).as_bytes())?;
-        file.write_all(&[self.active_flag])?;
-        file.write_all(&self.conference_number.to_le_bytes())?;
-        file.write_all(&self.logical_message_number.to_le_bytes())?;
-        file.write_all(&[self.net_tag])?;
-
+    /// Writes header and text blocks.
+    ///
+    /// # Remarks
+    /// In extended mode To, From & Subject fields longer than 25 characters are
+    /// written as kludge lines at the start of the text.
+    pub fn write<W: Write>(&self, file: &mut W, is_extended: bool) -> crate::Result<()> {
+        let mut body = Vec::new();
         if is_extended {
-            if self.to.len() > 25 {
-                file.write_all(b"To: ")?;
-                file.write_all(&self.to)?;
-                file.write_all(PCB_TXT_EOL_PTR)?;
-            }
-            if self.from.len() > 25 {
-                file.write_all(b"From: ")?;
-                file.write_all(&self.from)?;
-                file.write_all(PCB_TXT_EOL_PTR)?;
-            }
-
-            if self.subj.len() > 25 {
-                file.write_all(b"Subject: ")?;
-                file.write_all(&self.subj)?;
-                file.write_all(PCB_TXT_EOL_PTR)?;
+            let kludges: [(&[u8], &BString); 3] = [
+                (b"To: ", &self.to),
+                (b"From: ", &self.from),
+                (b"Subject: ", &self.subj),
+            ];
+            let mut has_kludges = false;
+            for (prefix, value) in kludges {
+                if value.len() > 25 {
+                    body.extend_from_slice(prefix);
+                    body.extend_from_slice(value);
+                    body.extend_from_slice(PCB_TXT_EOL_PTR);
+                    has_kludges = true;
+                }
             }
             // According to the spec after the kludge a blank line should be put.
-            if self.to.len() > 25 || self.from.len() > 25 || self.subj.len() > 25 {
-                file.write_all(PCB_TXT_EOL_PTR)?;
+            if has_kludges {
+                body.extend_from_slice(PCB_TXT_EOL_PTR);
             }
         }
+        body.extend(self.text.replace([b'\n'], PCB_TXT_EOL_PTR));
+        let num_blocks = body.len().div_ceil(Self::HEADER_SIZE) + 1;
+        body.resize((num_blocks - 1) * Self::HEADER_SIZE, b' ');
 
-        file.write_all(&self.text.to_vec().replace([b'\n'], PCB_TXT_EOL_PTR))?;
+        file.write_all(&[self.status])?;
+        file.write_all(&pad_field(self.msg_number.to_string().as_bytes(), 7))?;
+        file.write_all(&pad_field(&self.date_time, 13))?;
+        file.write_all(&pad_field(&self.to, 25))?;
+        file.write_all(&pad_field(&self.from, 25))?;
+        file.write_all(&pad_field(&self.subj, 25))?;
+        file.write_all(&pad_field(&self.password, 12))?;
+        file.write_all(&pad_field(self.ref_msg_number.to_string().as_bytes(), 8))?;
+        file.write_all(&pad_field(num_blocks.to_string().as_bytes(), 6))?;
+        file.write_all(&[self.active_flag])?;
+        file.write_all(&self.conference_number.to_le_bytes())?;
+        file.write_all(&self.logical_message_number.to_le_bytes())?;
+        file.write_all(&[self.net_tag])?;
+        file.write_all(&body)?;
         Ok(())
     }
 }
 
+/// Left justifies a field and fills it with spaces.
+fn pad_field(data: &[u8], len: usize) -> Vec<u8> {
+    let mut res = data[..data.len().min(len)].to_vec();
+    res.resize(len, b' ');
+    res
+}
+
 fn parse_qwk_number(data: &[u8]) -> crate::Result<u32> {
     let mut number = 0;
-    for &b in data {
+    // some readers right justify the numbers
+    for &b in data.trim_start_with(|c| c == ' ') {
         if b == b' ' || b == 0 {
             break;
         }
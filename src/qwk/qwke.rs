@@ -0,0 +1,414 @@
+//! QWKE extension files, see doc/qwk/qwke.txt
+use bstr::{BString, ByteSlice};
+
+use super::{split_word, QwkError};
+
+/// Flags of the AREA lines in TOREADER.EXT and TODOOR.EXT
+pub mod area_flags {
+    /// D - drop area (TODOOR.EXT only)
+    pub const DROP: u32 = 0x0000_0001;
+    /// a - all messages
+    pub const ALL: u32 = 0x0000_0002;
+    /// p - personal messages
+    pub const PERSONAL: u32 = 0x0000_0004;
+    /// g - personal messages and messages to 'ALL'
+    pub const GENERAL: u32 = 0x0000_0008;
+    /// w - include mail written by the user
+    pub const WRITTEN_BY_USER: u32 = 0x0000_0010;
+    /// k - include in keyword searches
+    pub const KEYWORD_SEARCH: u32 = 0x0000_0020;
+    /// f - include in filter exclude searches
+    pub const FILTER_SEARCH: u32 = 0x0000_0040;
+    /// F - forced to be read
+    pub const FORCED: u32 = 0x0000_0080;
+    /// B - blocked from getting replies
+    pub const BLOCKED: u32 = 0x0000_0100;
+    /// P - private mail only
+    pub const PRIVATE_ONLY: u32 = 0x0000_0200;
+    /// O - public mail only
+    pub const PUBLIC_ONLY: u32 = 0x0000_0400;
+    /// X - private & public mail
+    pub const PRIVATE_AND_PUBLIC: u32 = 0x0000_0800;
+    /// R - read only
+    pub const READ_ONLY: u32 = 0x0000_1000;
+    /// Z - no replies allowed
+    pub const NO_REPLIES: u32 = 0x0000_2000;
+    /// L - local area
+    pub const LOCAL: u32 = 0x0000_4000;
+    /// N - netmail area
+    pub const NETMAIL: u32 = 0x0000_8000;
+    /// E - echomail area
+    pub const ECHOMAIL: u32 = 0x0001_0000;
+    /// I - internet area
+    pub const INTERNET: u32 = 0x0002_0000;
+    /// U - newsgroup area
+    pub const NEWSGROUP: u32 = 0x0004_0000;
+    /// H - handles only
+    pub const HANDLES_ONLY: u32 = 0x0008_0000;
+    /// A - messages from any name allowed
+    pub const ANY_NAME: u32 = 0x0010_0000;
+    /// & - file attaches allowed
+    pub const ATTACHMENTS: u32 = 0x0020_0000;
+}
+
+/// Area flag characters, the flags are case sensitive
+const AREA_FLAG_MAP: [(u8, u32); 22] = [
+    (b'D', area_flags::DROP),
+    (b'a', area_flags::ALL),
+    (b'p', area_flags::PERSONAL),
+    (b'g', area_flags::GENERAL),
+    (b'w', area_flags::WRITTEN_BY_USER),
+    (b'k', area_flags::KEYWORD_SEARCH),
+    (b'f', area_flags::FILTER_SEARCH),
+    (b'F', area_flags::FORCED),
+    (b'B', area_flags::BLOCKED),
+    (b'P', area_flags::PRIVATE_ONLY),
+    (b'O', area_flags::PUBLIC_ONLY),
+    (b'X', area_flags::PRIVATE_AND_PUBLIC),
+    (b'R', area_flags::READ_ONLY),
+    (b'Z', area_flags::NO_REPLIES),
+    (b'L', area_flags::LOCAL),
+    (b'N', area_flags::NETMAIL),
+    (b'E', area_flags::ECHOMAIL),
+    (b'I', area_flags::INTERNET),
+    (b'U', area_flags::NEWSGROUP),
+    (b'H', area_flags::HANDLES_ONLY),
+    (b'A', area_flags::ANY_NAME),
+    (b'&', area_flags::ATTACHMENTS),
+];
+
+const EOL: &[u8; 1] = b"\n";
+
+/// AREA line - settings of a conference
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AreaSettings {
+    pub conference: u16,
+    /// See `area_flags`
+    pub flags: u32,
+}
+
+impl AreaSettings {
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn parse(data: &[u8]) -> crate::Result<Self> {
+        let (conference, settings) = split_word(data);
+        // unknown flags are ignored
+        let flags = settings
+            .iter()
+            .filter_map(|c| AREA_FLAG_MAP.iter().find(|(f, _)| f == c))
+            .fold(0, |flags, (_, flag)| flags | flag);
+        Ok(Self {
+            conference: parse_number(conference, data)?,
+            flags,
+        })
+    }
+
+    fn write(&self, s: &mut Vec<u8>) {
+        s.extend(self.conference.to_string().as_bytes());
+        s.push(b' ');
+        s.extend(
+            AREA_FLAG_MAP
+                .iter()
+                .filter(|(_, flag)| self.has_flag(*flag))
+                .map(|(c, _)| *c),
+        );
+    }
+}
+
+/// BULL/FILE line - file name with a description
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileDescription {
+    pub file_name: BString,
+    /// Optional for FILE lines
+    pub description: BString,
+}
+
+impl FileDescription {
+    fn parse(data: &[u8]) -> Self {
+        let (file_name, description) = split_word(data);
+        Self {
+            file_name: file_name.into(),
+            description: description.into(),
+        }
+    }
+
+    fn write(&self, s: &mut Vec<u8>) {
+        s.extend(self.file_name.bytes());
+        if !self.description.is_empty() {
+            s.push(b' ');
+            s.extend(self.description.bytes());
+        }
+    }
+}
+
+/// TOREADER.EXT ATTACH line - file attached to a message in the packet
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MessageAttachment {
+    pub file_name: BString,
+    pub conference: u16,
+    pub message_number: u32,
+}
+
+/// TODOOR.EXT ATTACH line - file attached to a message in the reply packet
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplyAttachment {
+    pub file_name: BString,
+    /// 1 based number of the message in the reply packet
+    pub reply_number: u32,
+}
+
+/// TODOOR.EXT RESET line - resets the last read pointer of a conference
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PointerReset {
+    pub conference: u16,
+    /// Number of messages from the end, `None` resets to the start of the message base
+    pub messages: Option<u32>,
+}
+
+/// TODOOR.EXT REQUEST line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileRequest {
+    /// File of the BBS file areas
+    File(BString),
+    /// Files attached to a message
+    Attachment {
+        conference: u16,
+        message_number: u32,
+    },
+}
+
+/// TODOOR.EXT KEYWORD/FILTER/TWIT line, lists are only changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListChange {
+    Add(BString),
+    /// Written with a leading '-'
+    Remove(BString),
+}
+
+impl ListChange {
+    fn parse(data: &[u8]) -> Self {
+        match data.strip_prefix(b"-") {
+            Some(entry) => ListChange::Remove(entry.into()),
+            None => ListChange::Add(data.into()),
+        }
+    }
+
+    fn write(&self, s: &mut Vec<u8>) {
+        match self {
+            ListChange::Add(entry) => s.extend(entry.bytes()),
+            ListChange::Remove(entry) => {
+                s.push(b'-');
+                s.extend(entry.bytes());
+            }
+        }
+    }
+}
+
+/// TOREADER.EXT - information from the door for the reader.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToReaderExt {
+    /// Alias name of the user
+    pub alias: Option<BString>,
+    /// Selected areas
+    pub areas: Vec<AreaSettings>,
+    /// Descriptions of the BLT-x.y files
+    pub bulletins: Vec<FileDescription>,
+    pub attachments: Vec<MessageAttachment>,
+    /// Requested files included in the packet
+    pub files: Vec<FileDescription>,
+    pub keywords: Vec<BString>,
+    pub filters: Vec<BString>,
+    pub twits: Vec<BString>,
+}
+
+impl ToReaderExt {
+    pub const FILE_NAME: &'static str = "toreader.ext";
+
+    /// Parses TOREADER.EXT, unknown identifiers are ignored.
+    pub fn read(data: &[u8]) -> crate::Result<Self> {
+        let mut res = Self::default();
+        for line in data.lines() {
+            let (identifier, arguments) = split_word(line);
+            match identifier.to_ascii_uppercase().as_slice() {
+                b"ALIAS" => res.alias = Some(arguments.into()),
+                b"AREA" => res.areas.push(AreaSettings::parse(arguments)?),
+                b"BULL" => res.bulletins.push(FileDescription::parse(arguments)),
+                b"ATTACH" => {
+                    let (file_name, rest) = split_word(arguments);
+                    let (conference, message_number) = split_word(rest);
+                    res.attachments.push(MessageAttachment {
+                        file_name: file_name.into(),
+                        conference: parse_number(conference, line)?,
+                        message_number: parse_number(message_number, line)?,
+                    });
+                }
+                b"FILE" => res.files.push(FileDescription::parse(arguments)),
+                b"KEYWORD" => res.keywords.push(arguments.into()),
+                b"FILTER" => res.filters.push(arguments.into()),
+                b"TWIT" => res.twits.push(arguments.into()),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut s = Vec::new();
+        if let Some(alias) = &self.alias {
+            write_line(&mut s, b"ALIAS", |s| s.extend(alias.bytes()));
+        }
+        for area in &self.areas {
+            write_line(&mut s, b"AREA", |s| area.write(s));
+        }
+        for bulletin in &self.bulletins {
+            write_line(&mut s, b"BULL", |s| bulletin.write(s));
+        }
+        for attachment in &self.attachments {
+            write_line(&mut s, b"ATTACH", |s| {
+                s.extend(attachment.file_name.bytes());
+                s.extend(
+                    format!(" {} {}", attachment.conference, attachment.message_number).as_bytes(),
+                );
+            });
+        }
+        for file in &self.files {
+            write_line(&mut s, b"FILE", |s| file.write(s));
+        }
+        for (identifier, entries) in [
+            (b"KEYWORD" as &[u8], &self.keywords),
+            (b"FILTER", &self.filters),
+            (b"TWIT", &self.twits),
+        ] {
+            for entry in entries {
+                write_line(&mut s, identifier, |s| s.extend(entry.bytes()));
+            }
+        }
+        s
+    }
+}
+
+/// TODOOR.EXT - changes from the reader for the door.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToDoorExt {
+    /// Changed areas
+    pub areas: Vec<AreaSettings>,
+    pub resets: Vec<PointerReset>,
+    pub attachments: Vec<ReplyAttachment>,
+    /// Uploaded files included in the reply packet
+    pub files: Vec<FileDescription>,
+    pub requests: Vec<FileRequest>,
+    pub keywords: Vec<ListChange>,
+    pub filters: Vec<ListChange>,
+    pub twits: Vec<ListChange>,
+}
+
+impl ToDoorExt {
+    pub const FILE_NAME: &'static str = "todoor.ext";
+
+    /// Parses TODOOR.EXT, unknown identifiers are ignored.
+    pub fn read(data: &[u8]) -> crate::Result<Self> {
+        let mut res = Self::default();
+        for line in data.lines() {
+            let (identifier, arguments) = split_word(line);
+            match identifier.to_ascii_uppercase().as_slice() {
+                b"AREA" => res.areas.push(AreaSettings::parse(arguments)?),
+                b"RESET" => {
+                    let (conference, messages) = split_word(arguments);
+                    res.resets.push(PointerReset {
+                        conference: parse_number(conference, line)?,
+                        messages: if messages.is_empty() {
+                            None
+                        } else {
+                            Some(parse_number(messages, line)?)
+                        },
+                    });
+                }
+                b"ATTACH" => {
+                    let (file_name, reply_number) = split_word(arguments);
+                    res.attachments.push(ReplyAttachment {
+                        file_name: file_name.into(),
+                        reply_number: parse_number(reply_number, line)?,
+                    });
+                }
+                b"FILE" => res.files.push(FileDescription::parse(arguments)),
+                b"REQUEST" => {
+                    let (first, message_number) = split_word(arguments);
+                    let request = if message_number.is_empty() {
+                        FileRequest::File(first.into())
+                    } else {
+                        FileRequest::Attachment {
+                            conference: parse_number(first, line)?,
+                            message_number: parse_number(message_number, line)?,
+                        }
+                    };
+                    res.requests.push(request);
+                }
+                b"KEYWORD" => res.keywords.push(ListChange::parse(arguments)),
+                b"FILTER" => res.filters.push(ListChange::parse(arguments)),
+                b"TWIT" => res.twits.push(ListChange::parse(arguments)),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut s = Vec::new();
+        for area in &self.areas {
+            write_line(&mut s, b"AREA", |s| area.write(s));
+        }
+        for reset in &self.resets {
+            write_line(&mut s, b"RESET", |s| {
+                s.extend(reset.conference.to_string().as_bytes());
+                if let Some(messages) = reset.messages {
+                    s.extend(format!(" {}", messages).as_bytes());
+                }
+            });
+        }
+        for attachment in &self.attachments {
+            write_line(&mut s, b"ATTACH", |s| {
+                s.extend(attachment.file_name.bytes());
+                s.extend(format!(" {}", attachment.reply_number).as_bytes());
+            });
+        }
+        for file in &self.files {
+            write_line(&mut s, b"FILE", |s| file.write(s));
+        }
+        for request in &self.requests {
+            write_line(&mut s, b"REQUEST", |s| match request {
+                FileRequest::File(file_name) => s.extend(file_name.bytes()),
+                FileRequest::Attachment {
+                    conference,
+                    message_number,
+                } => s.extend(format!("{} {}", conference, message_number).as_bytes()),
+            });
+        }
+        for (identifier, changes) in [
+            (b"KEYWORD" as &[u8], &self.keywords),
+            (b"FILTER", &self.filters),
+            (b"TWIT", &self.twits),
+        ] {
+            for change in changes {
+                write_line(&mut s, identifier, |s| change.write(s));
+            }
+        }
+        s
+    }
+}
+
+fn write_line(s: &mut Vec<u8>, identifier: &[u8], write_arguments: impl FnOnce(&mut Vec<u8>)) {
+    s.extend(identifier);
+    s.push(b' ');
+    write_arguments(s);
+    s.extend(EOL);
+}
+
+fn parse_number<T: std::str::FromStr>(number: &[u8], line: &[u8]) -> crate::Result<T> {
+    number
+        .to_str()
+        .ok()
+        .and_then(|number| number.parse().ok())
+        .ok_or_else(|| QwkError::InvalidExtensionLine(line.into()).into())
+}
@@ -0,0 +1,175 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bstr::{BString, ByteSlice};
+
+use super::{
+    qwk_message::QWKMessage,
+    qwke::ToDoorExt,
+    storage::{write_archive, PacketStorage},
+    QwkError,
+};
+
+/// Reply packet (BBSID.REP) content uploaded from offline readers.
+///
+/// # Remarks
+/// BBSID.MSG has the MESSAGES.DAT format, but the packet header block starts with the
+/// BBSID and the message number field of every message contains the conference number.
+#[derive(Debug, Default, Clone)]
+pub struct RepPacket {
+    pub bbs_id: BString,
+    /// Replies, the target conference is stored in `conference_number`
+    pub messages: Vec<QWKMessage>,
+    /// QWKE TODOOR.EXT file of the packet
+    pub todoor_ext: Option<ToDoorExt>,
+}
+
+impl RepPacket {
+    pub fn new(bbs_id: BString) -> Self {
+        Self {
+            bbs_id,
+            messages: Vec::new(),
+            todoor_ext: None,
+        }
+    }
+
+    /// Reads a BBSID.MSG file and the TODOOR.EXT file next to it.
+    pub fn read<P: AsRef<Path>>(path: P, is_extended: bool) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let mut res = Self::read_from(&mut BufReader::new(File::open(path)?), is_extended)?;
+        if let Some(dir) = path.parent() {
+            res.todoor_ext = read_todoor_ext(&PacketStorage::Directory(dir.to_path_buf()))?;
+        }
+        Ok(res)
+    }
+
+    /// Reads a reply packet archive (BBSID.REP), the BBSID is taken from the archive name.
+    pub fn read_rep<P: AsRef<Path>>(path: P, is_extended: bool) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let bbs_id = path.file_stem().map(|stem| stem.to_string_lossy());
+        Self::read_archive(File::open(path)?, bbs_id.as_deref(), is_extended)
+    }
+
+    /// Reads the BBSID.MSG file of a reply packet archive (BBSID.REP).
+    ///
+    /// # Remarks
+    /// Without a BBSID the archive needs to contain exactly one .MSG file.
+    pub fn read_archive<R: Read + Seek>(
+        reader: R,
+        bbs_id: Option<&str>,
+        is_extended: bool,
+    ) -> crate::Result<Self> {
+        let storage = PacketStorage::open_archive(reader)?;
+        let name = find_msg_file(&storage, bbs_id)?;
+        let mut res = Self::read_from(&mut storage.open(&name)?, is_extended)?;
+        res.todoor_ext = read_todoor_ext(&storage)?;
+        Ok(res)
+    }
+
+    fn read_from<R: Read + Seek>(reader: &mut R, is_extended: bool) -> crate::Result<Self> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0; QWKMessage::HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let bbs_id = header
+            .fields_with(|c| c.is_ascii_whitespace() || c == '\0')
+            .next()
+            .unwrap_or_default();
+
+        let mut messages = Vec::new();
+        while reader.stream_position()? < size {
+            let mut msg = QWKMessage::read(reader, is_extended)?;
+            // Some readers leave the conference number field empty
+            msg.conference_number = msg.msg_number as u16;
+            messages.push(msg);
+        }
+        Ok(Self {
+            bbs_id: bbs_id.into(),
+            messages,
+            todoor_ext: None,
+        })
+    }
+
+    pub fn add_message(&mut self, msg: QWKMessage) {
+        self.messages.push(msg);
+    }
+
+    /// Replies to a conference
+    pub fn conference_messages(&self, conference: u16) -> impl Iterator<Item = &QWKMessage> {
+        self.messages
+            .iter()
+            .filter(move |msg| msg.conference_number == conference)
+    }
+
+    /// Writes the replies to a BBSID.MSG file in a directory and returns its path.
+    pub fn write<P: AsRef<Path>>(&self, path: P, is_extended: bool) -> crate::Result<PathBuf> {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        let file_name = path.join(self.file_name());
+        let mut writer = BufWriter::new(File::create(&file_name)?);
+        writer.write_all(&self.write_msg_file(is_extended)?)?;
+        writer.flush()?;
+        if let Some(todoor_ext) = &self.todoor_ext {
+            fs::write(path.join(ToDoorExt::FILE_NAME), todoor_ext.write())?;
+        }
+        Ok(file_name)
+    }
+
+    /// Writes the reply packet as ZIP archive (BBSID.REP) containing BBSID.MSG.
+    pub fn write_archive<W: Write + Seek>(&self, writer: W, is_extended: bool) -> crate::Result<W> {
+        let mut files = vec![(self.file_name(), self.write_msg_file(is_extended)?)];
+        if let Some(todoor_ext) = &self.todoor_ext {
+            files.push((ToDoorExt::FILE_NAME.to_string(), todoor_ext.write()));
+        }
+        write_archive(writer, &files)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.MSG", self.bbs_id.to_ascii_uppercase().as_bstr())
+    }
+
+    fn write_msg_file(&self, is_extended: bool) -> crate::Result<Vec<u8>> {
+        let mut res = self.bbs_id.to_ascii_uppercase();
+        res.resize(QWKMessage::HEADER_SIZE, b' ');
+        for msg in &self.messages {
+            let mut msg = msg.clone();
+            msg.msg_number = msg.conference_number as u32;
+            msg.write(&mut res, is_extended)?;
+        }
+        Ok(res)
+    }
+}
+
+fn find_msg_file(storage: &PacketStorage, bbs_id: Option<&str>) -> crate::Result<String> {
+    if let Some(bbs_id) = bbs_id {
+        let name = format!("{}.MSG", bbs_id.to_ascii_uppercase());
+        if !storage.exists(&name) {
+            return Err(QwkError::FileNotFound(name).into());
+        }
+        return Ok(name);
+    }
+    let mut candidates: Vec<String> = storage
+        .file_names()
+        .into_iter()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".msg"))
+        .collect();
+    match candidates.len() {
+        0 => Err(QwkError::FileNotFound("BBSID.MSG".to_string()).into()),
+        1 => Ok(candidates.remove(0)),
+        _ => {
+            candidates.sort();
+            Err(QwkError::MultipleMessageFiles(candidates.join(", ")).into())
+        }
+    }
+}
+
+fn read_todoor_ext(storage: &PacketStorage) -> crate::Result<Option<ToDoorExt>> {
+    if !storage.exists(ToDoorExt::FILE_NAME) {
+        return Ok(None);
+    }
+    Ok(Some(ToDoorExt::read(&storage.read(ToDoorExt::FILE_NAME)?)?))
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::QwkError;
+
+pub(crate) trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Location of the files of a QWK or REP packet.
+///
+/// # Remarks
+/// File names are case insensitive, packets created on DOS use upper case names.
+pub(crate) enum PacketStorage {
+    /// Extracted packet
+    Directory(PathBuf),
+    /// Files of a packet archive by lower case file name
+    Archive(HashMap<String, Vec<u8>>),
+}
+
+/// Maximum uncompressed size of a single file in a packet archive
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Maximum uncompressed size of all files in a packet archive
+const MAX_PACKET_SIZE: u64 = 256 * 1024 * 1024;
+
+impl PacketStorage {
+    /// Reads all files of a ZIP archive, packets are small enough to keep them in memory.
+    pub fn open_archive<R: Read + Seek>(reader: R) -> crate::Result<Self> {
+        Self::open_archive_with_limits(reader, MAX_FILE_SIZE, MAX_PACKET_SIZE)
+    }
+
+    /// Reads all files of a ZIP archive.
+    ///
+    /// # Remarks
+    /// Packets are uploaded by users, so the sizes stored in the archive aren't trusted.
+    /// Reading fails if a file or the whole packet decompresses to more than the limit.
+    pub(crate) fn open_archive_with_limits<R: Read + Seek>(
+        reader: R,
+        max_file_size: u64,
+        max_packet_size: u64,
+    ) -> crate::Result<Self> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut files = HashMap::new();
+        let mut total_size = 0;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if !file.is_file() {
+                continue;
+            }
+            let limit = max_file_size.min(max_packet_size - total_size);
+            let mut data = Vec::new();
+            (&mut file).take(limit + 1).read_to_end(&mut data)?;
+            if data.len() as u64 > limit {
+                if limit < max_file_size {
+                    return Err(QwkError::PacketTooLarge(max_packet_size).into());
+                }
+                return Err(QwkError::FileTooLarge(file.name().to_string()).into());
+            }
+            total_size += data.len() as u64;
+            files.insert(file.name().to_ascii_lowercase(), data);
+        }
+        Ok(PacketStorage::Archive(files))
+    }
+
+    /// File names in the packet
+    pub fn file_names(&self) -> Vec<String> {
+        match self {
+            PacketStorage::Directory(path) => fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.path().is_file())
+                        .map(|entry| entry.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            PacketStorage::Archive(files) => files.keys().cloned().collect(),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        match self {
+            PacketStorage::Directory(path) => find_file(path, name).is_some(),
+            PacketStorage::Archive(files) => files.contains_key(&name.to_ascii_lowercase()),
+        }
+    }
+
+    pub fn read(&self, name: &str) -> crate::Result<Vec<u8>> {
+        match self {
+            PacketStorage::Directory(path) => Ok(fs::read(get_file(path, name)?)?),
+            PacketStorage::Archive(files) => Ok(get_data(files, name)?.clone()),
+        }
+    }
+
+    pub fn open(&self, name: &str) -> crate::Result<Box<dyn ReadSeek + '_>> {
+        match self {
+            PacketStorage::Directory(path) => {
+                Ok(Box::new(BufReader::new(File::open(get_file(path, name)?)?)))
+            }
+            PacketStorage::Archive(files) => Ok(Box::new(Cursor::new(get_data(files, name)?))),
+        }
+    }
+}
+
+fn get_data<'a>(files: &'a HashMap<String, Vec<u8>>, name: &str) -> crate::Result<&'a Vec<u8>> {
+    files
+        .get(&name.to_ascii_lowercase())
+        .ok_or_else(|| QwkError::FileNotFound(name.to_string()).into())
+}
+
+fn get_file(path: &Path, name: &str) -> crate::Result<PathBuf> {
+    find_file(path, name).ok_or_else(|| QwkError::FileNotFound(name.to_string()).into())
+}
+
+fn find_file(path: &Path, name: &str) -> Option<PathBuf> {
+    let file = path.join(name);
+    if file.is_file() {
+        return Some(file);
+    }
+    fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+        .map(|entry| entry.path())
+}
+
+/// Writes packet files to a ZIP archive.
+pub(crate) fn write_archive<W: Write + Seek>(
+    writer: W,
+    files: &[(String, Vec<u8>)],
+) -> crate::Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?)
+}
+
+/// Writes packet files to a directory.
+pub(crate) fn write_directory(path: &Path, files: &[(String, Vec<u8>)]) -> crate::Result<()> {
+    fs::create_dir_all(path)?;
+    for (name, data) in files {
+        fs::write(path.join(name), data)?;
+    }
+    Ok(())
+}
@@ -1,9 +1,19 @@
 use super::{
     control::{Conference, ControlDat},
+    door_id::{control_types, DoorId},
+    packet_builder::QwkPacketBuilder,
+    qwk_message::QWKMessage,
+    qwke::{
+        area_flags, AreaSettings, FileRequest, ListChange, PointerReset, ToDoorExt, ToReaderExt,
+    },
+    rep::RepPacket,
+    storage::PacketStorage,
     QwkMessageBase,
 };
-use bstr::BString;
+use crate::message_base::{Message, MessageBase};
+use bstr::{BString, ByteSlice};
 use pretty_assertions::assert_eq;
+use tempfile::TempDir;
 
 const TEST_CONTROL_DAT: &[u8; 114] = b"My BBS
 New York
@@ -15,7 +25,7 @@ JANE DOE
 
 0
 999
-1
+0
 0
 Main Board
 HELLO
@@ -77,6 +87,8 @@ fn read_qwk_repo() {
         b"\xAE\xAE PCBoard Professional Bulletin Board \xAF\xAF".to_vec()
     );
     assert_eq!(msg_base.get_bbs_sysop_name(), "Sysop, Sysop");
+    assert_eq!(msg_base.get_conferences().len(), 1);
+    assert_eq!(msg_base.get_conferences()[0].name, "Main Board");
 
     let mail = msg_base.read_conference_mail(0).unwrap();
     assert_eq!(mail.len(), 1);
@@ -106,3 +118,505 @@ fn test_index() {
     let res = QwkMessageBase::convert_qwk_index(&in_data).unwrap();
     assert_eq!(res, out_data);
 }
+
+#[test]
+fn test_write_packet() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let mut control_dat = ControlDat::read(TEST_CONTROL_DAT).unwrap();
+    control_dat.conferences.push(Conference {
+        number: 1,
+        name: BString::from("Second Board"),
+    });
+    let mut builder = QwkPacketBuilder::new(control_dat)
+        .with_extended(true)
+        .with_door_id(DoorId::new("jamjam", "0.2"))
+        .with_welcome_screen(BString::from("Welcome"));
+
+    let subject = "A subject that doesn't fit into the 25 chars of the header";
+    let messages = [
+        (0, "JANE DOE", "Hello", "first\nmessage\n"),
+        (1, "ALL", subject, "second message\n"),
+        (0, "ALL", "Bye", "third message\n"),
+    ];
+    for (i, (conference, to, subject, text)) in messages.iter().enumerate() {
+        let msg = Message {
+            number: i as u32 + 1,
+            from: BString::from("SYSOP"),
+            to: BString::from(*to),
+            subject: BString::from(*subject),
+            text: BString::from(*text),
+            ..Default::default()
+        };
+        builder.add_message(QWKMessage::from_message(&msg, *conference));
+    }
+    builder.write(tmpdir.path()).unwrap();
+
+    let msg_base = QwkMessageBase::open(tmpdir.path(), true).unwrap();
+    assert_eq!(msg_base.get_message_count(), 3);
+    assert_eq!(msg_base.get_conferences().len(), 2);
+
+    let mail = msg_base.read_conference_mail(0).unwrap();
+    assert_eq!(mail.len(), 2);
+    assert_eq!(mail[0].to, "JANE DOE");
+    assert_eq!(mail[0].text.trim_end(), b"first\nmessage");
+    assert_eq!(mail[1].subj, "Bye");
+    assert_eq!(mail[1].logical_message_number, 3);
+
+    let mail = msg_base.read_conference_mail(1).unwrap();
+    assert_eq!(mail.len(), 1);
+    assert_eq!(mail[0].subj, subject);
+    assert_eq!(mail[0].text.trim_end(), b"second message");
+
+    let personal = QwkMessageBase::read_qwk_index(tmpdir.path().join("personal.ndx")).unwrap();
+    assert_eq!(personal, vec![2]);
+    let door_id = msg_base.read_door_id().unwrap().unwrap();
+    assert_eq!(door_id.door, "jamjam");
+    assert_eq!(door_id.version, "0.2");
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("HELLO")).unwrap(),
+        b"Welcome"
+    );
+    assert!(!tmpdir.path().join("NEWS").exists());
+}
+
+#[test]
+fn test_rep_packet() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let subject = "A reply subject that is longer than 25 chars";
+    let mut rep = RepPacket::new(BString::from("mybbs"));
+    for (conference, subject) in [(3, "Re: Hello"), (12, subject)] {
+        let msg = Message {
+            from: BString::from("JANE DOE"),
+            to: BString::from("ALL"),
+            subject: BString::from(subject),
+            text: BString::from("reply text\n"),
+            ..Default::default()
+        };
+        rep.add_message(QWKMessage::from_message(&msg, conference));
+    }
+    let file_name = rep.write(tmpdir.path(), true).unwrap();
+    assert_eq!(file_name, tmpdir.path().join("MYBBS.MSG"));
+
+    let data = std::fs::read(&file_name).unwrap();
+    assert!(data.starts_with(b"MYBBS "));
+    // conference number is stored in the message number field
+    assert_eq!(&data[129..136], b"3      ");
+
+    let rep = RepPacket::read(&file_name, true).unwrap();
+    assert_eq!(rep.bbs_id, "MYBBS");
+    assert_eq!(rep.messages.len(), 2);
+    assert_eq!(rep.messages[0].conference_number, 3);
+    assert_eq!(rep.messages[0].subj, "Re: Hello");
+    assert_eq!(rep.messages[1].conference_number, 12);
+    assert_eq!(rep.messages[1].subj, subject);
+    assert_eq!(rep.messages[1].text.trim_end(), b"reply text");
+    assert_eq!(rep.conference_messages(12).count(), 1);
+}
+
+#[test]
+fn test_rep_packet_conference_field() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let msg = QWKMessage::from_message(&Message::default(), 0);
+    let mut data = Vec::new();
+    msg.write(&mut data, false).unwrap();
+    // readers that right justify the conference number & leave the binary field empty
+    data[1..8].copy_from_slice(b"     42");
+
+    let mut file = b"MYBBS".to_vec();
+    file.resize(128, b' ');
+    file.extend_from_slice(&data);
+    let file_name = tmpdir.path().join("MYBBS.MSG");
+    std::fs::write(&file_name, file).unwrap();
+
+    let rep = RepPacket::read(&file_name, true).unwrap();
+    assert_eq!(rep.messages.len(), 1);
+    assert_eq!(rep.messages[0].conference_number, 42);
+}
+
+fn build_test_packet() -> QwkPacketBuilder {
+    let control_dat = ControlDat::read(TEST_CONTROL_DAT).unwrap();
+    let mut builder = QwkPacketBuilder::new(control_dat).with_welcome_screen(BString::from("Hi"));
+    let msg = Message {
+        from: BString::from("SYSOP"),
+        to: BString::from("JANE DOE"),
+        subject: BString::from("Packed"),
+        text: BString::from("zipped message\n"),
+        ..Default::default()
+    };
+    builder.add_message(QWKMessage::from_message(&msg, 0));
+    builder
+}
+
+#[test]
+fn test_packet_archive() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let data = build_test_packet()
+        .write_archive(std::io::Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+    let file_name = tmpdir.path().join("MYBBS.QWK");
+    std::fs::write(&file_name, &data).unwrap();
+
+    let msg_base = QwkMessageBase::open_archive(std::io::Cursor::new(data), true).unwrap();
+    let mail = msg_base.read_conference_mail(0).unwrap();
+    assert_eq!(mail.len(), 1);
+    assert_eq!(mail[0].subj, "Packed");
+    assert_eq!(msg_base.iter().count(), 1);
+    assert_eq!(msg_base.read_file("HELLO").unwrap(), b"Hi");
+
+    let msg_base = crate::message_base::open_message_base(&file_name).unwrap();
+    assert_eq!(
+        msg_base.format(),
+        crate::message_base::MessageBaseFormat::Qwk
+    );
+    assert_eq!(msg_base.active_messages(), 1);
+}
+
+#[test]
+fn test_packet_upper_case_names() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    build_test_packet().write(tmpdir.path()).unwrap();
+    for name in ["control.dat", "messages.dat", "000.ndx"] {
+        std::fs::rename(
+            tmpdir.path().join(name),
+            tmpdir.path().join(name.to_ascii_uppercase()),
+        )
+        .unwrap();
+    }
+
+    let msg_base = QwkMessageBase::open(tmpdir.path(), true).unwrap();
+    let mail = msg_base.read_conference_mail(0).unwrap();
+    assert_eq!(mail.len(), 1);
+    assert_eq!(mail[0].text.trim_end(), b"zipped message");
+}
+
+#[test]
+fn test_rep_archive() {
+    let mut rep = RepPacket::new(BString::from("MYBBS"));
+    let msg = Message {
+        subject: BString::from("Re: Packed"),
+        ..Default::default()
+    };
+    rep.add_message(QWKMessage::from_message(&msg, 7));
+    let data = rep
+        .write_archive(std::io::Cursor::new(Vec::new()), true)
+        .unwrap()
+        .into_inner();
+
+    let rep = RepPacket::read_archive(std::io::Cursor::new(&data), None, true).unwrap();
+    assert_eq!(rep.bbs_id, "MYBBS");
+    assert_eq!(rep.messages.len(), 1);
+    assert_eq!(rep.messages[0].conference_number, 7);
+    assert_eq!(rep.messages[0].subj, "Re: Packed");
+
+    let rep = RepPacket::read_archive(std::io::Cursor::new(&data), Some("mybbs"), true).unwrap();
+    assert_eq!(rep.messages.len(), 1);
+    assert!(RepPacket::read_archive(std::io::Cursor::new(&data), Some("OTHER"), true).is_err());
+}
+
+#[test]
+fn test_rep_archive_multiple_msg_files() {
+    let files = vec![
+        ("MYBBS.MSG".to_string(), vec![b' '; 128]),
+        ("OTHER.MSG".to_string(), vec![b' '; 128]),
+    ];
+    let data = super::storage::write_archive(std::io::Cursor::new(Vec::new()), &files)
+        .unwrap()
+        .into_inner();
+    let err = RepPacket::read_archive(std::io::Cursor::new(&data), None, true).unwrap_err();
+    assert!(err.to_string().contains("more than one .MSG file"));
+
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let path = tmpdir.path().join("OTHER.REP");
+    std::fs::write(&path, &data).unwrap();
+    let rep = RepPacket::read_rep(&path, true).unwrap();
+    assert!(rep.messages.is_empty());
+}
+
+#[test]
+fn test_archive_size_limit() {
+    let files = vec![
+        ("a.dat".to_string(), vec![0; 100]),
+        ("b.dat".to_string(), vec![0; 100]),
+    ];
+    let data = super::storage::write_archive(std::io::Cursor::new(Vec::new()), &files)
+        .unwrap()
+        .into_inner();
+    let open = |max_file_size, max_packet_size| {
+        PacketStorage::open_archive_with_limits(
+            std::io::Cursor::new(&data),
+            max_file_size,
+            max_packet_size,
+        )
+    };
+    assert!(open(100, 200).is_ok());
+    assert!(open(99, 200)
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("a.dat exceeds the size limit"));
+    assert!(open(100, 199)
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("limit of 199 bytes"));
+}
+
+const TEST_TOREADER_EXT: &[u8] = b"ALIAS Rawhide
+AREA 23 awOU
+AREA 172 gwkfPI
+BULL BLT-1.4 System Stats
+ATTACH TEST.ZIP 12 782
+FILE GOODGAME.ARJ This is a great new SVGA action game!
+FILE OTHER.ARJ
+KEYWORD olms
+FILTER hacking
+TWIT Death Wizard
+";
+
+#[test]
+fn test_toreader_ext() {
+    let ext = ToReaderExt::read(TEST_TOREADER_EXT).unwrap();
+    assert_eq!(ext.alias, Some(BString::from("Rawhide")));
+    assert_eq!(ext.areas.len(), 2);
+    assert_eq!(ext.areas[0].conference, 23);
+    assert_eq!(
+        ext.areas[0].flags,
+        area_flags::ALL
+            | area_flags::WRITTEN_BY_USER
+            | area_flags::PUBLIC_ONLY
+            | area_flags::NEWSGROUP
+    );
+    assert!(ext.areas[1].has_flag(area_flags::INTERNET));
+    assert!(!ext.areas[1].has_flag(area_flags::ALL));
+    assert_eq!(ext.bulletins[0].description, "System Stats");
+    assert_eq!(ext.attachments[0].conference, 12);
+    assert_eq!(ext.attachments[0].message_number, 782);
+    assert_eq!(ext.files[1].file_name, "OTHER.ARJ");
+    assert!(ext.files[1].description.is_empty());
+    assert_eq!(ext.twits, vec![BString::from("Death Wizard")]);
+
+    assert_eq!(ext.write(), TEST_TOREADER_EXT);
+    assert!(ToReaderExt::read(b"AREA x a").is_err());
+}
+
+#[test]
+fn test_todoor_ext() {
+    let data = b"AREA 23 D\r\nAREA 44 gf\r\nRESET 23 100\r\nRESET 44\r\nATTACH ATTACHME.ZIP 3\r\n\
+FILE GOODGAME.ZIP This is a great new game!\r\nREQUEST bob.zip\r\nREQUEST 12 333\r\n\
+KEYWORD -bob\r\nTWIT Death Wizard\r\nUNKNOWN line\r\n";
+    let ext = ToDoorExt::read(data).unwrap();
+    assert_eq!(
+        ext.areas,
+        vec![
+            AreaSettings {
+                conference: 23,
+                flags: area_flags::DROP
+            },
+            AreaSettings {
+                conference: 44,
+                flags: area_flags::GENERAL | area_flags::FILTER_SEARCH
+            }
+        ]
+    );
+    assert_eq!(
+        ext.resets,
+        vec![
+            PointerReset {
+                conference: 23,
+                messages: Some(100)
+            },
+            PointerReset {
+                conference: 44,
+                messages: None
+            }
+        ]
+    );
+    assert_eq!(ext.attachments[0].reply_number, 3);
+    assert_eq!(
+        ext.requests,
+        vec![
+            FileRequest::File(BString::from("bob.zip")),
+            FileRequest::Attachment {
+                conference: 12,
+                message_number: 333
+            }
+        ]
+    );
+    assert_eq!(ext.keywords, vec![ListChange::Remove(BString::from("bob"))]);
+    assert_eq!(
+        ext.twits,
+        vec![ListChange::Add(BString::from("Death Wizard"))]
+    );
+
+    assert_eq!(ToDoorExt::read(&ext.write()).unwrap(), ext);
+}
+
+#[test]
+fn test_qwke_packet_files() {
+    let toreader_ext = ToReaderExt::read(TEST_TOREADER_EXT).unwrap();
+    let data = build_test_packet()
+        .with_toreader_ext(toreader_ext.clone())
+        .write_archive(std::io::Cursor::new(Vec::new()))
+        .unwrap()
+        .into_inner();
+    let msg_base = QwkMessageBase::open_archive(std::io::Cursor::new(data), true).unwrap();
+    assert_eq!(msg_base.read_toreader_ext().unwrap(), Some(toreader_ext));
+    let msg_base = QwkMessageBase::open("data/qwk", true).unwrap();
+    assert_eq!(msg_base.read_toreader_ext().unwrap(), None);
+
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let mut rep = RepPacket::new(BString::from("MYBBS"));
+    rep.todoor_ext = Some(ToDoorExt {
+        resets: vec![PointerReset {
+            conference: 1,
+            messages: None,
+        }],
+        ..Default::default()
+    });
+    let file_name = rep.write(tmpdir.path(), true).unwrap();
+    assert_eq!(
+        RepPacket::read(file_name, true).unwrap().todoor_ext,
+        rep.todoor_ext
+    );
+    let data = rep
+        .write_archive(std::io::Cursor::new(Vec::new()), true)
+        .unwrap()
+        .into_inner();
+    let read = RepPacket::read_archive(std::io::Cursor::new(data), None, true).unwrap();
+    assert_eq!(read.todoor_ext, rep.todoor_ext);
+}
+
+#[test]
+fn test_door_id() {
+    let data = b"DOOR = Tomcat\r\nVERSION = 2.9\r\nSYSTEM = Wildcat 2.55\r\n\
+CONTROLNAME = TOMCAT\r\nCONTROLTYPE = ADD\r\nCONTROLTYPE = drop\r\n\
+CONTROLTYPE = MAXKEYWORDS 10\r\nRECEIPT\r\nMIXEDCASE = YES\r\n";
+    let door_id = DoorId::read(data);
+    assert_eq!(door_id.door, "Tomcat");
+    assert_eq!(door_id.system, "Wildcat 2.55");
+    assert_eq!(door_id.control_name, "TOMCAT");
+    assert!(door_id.accepts(control_types::ADD));
+    assert!(door_id.accepts(control_types::DROP));
+    assert!(!door_id.accepts(control_types::REQUEST));
+    assert_eq!(
+        door_id.get_control_value(control_types::MAX_KEYWORDS),
+        Some(10)
+    );
+    assert_eq!(door_id.get_control_value(control_types::ADD), None);
+    assert!(door_id.receipt);
+    assert!(door_id.mixed_case);
+    assert!(!door_id.fido_tag);
+    assert_eq!(DoorId::read(&door_id.write()), door_id);
+
+    let door_id = DoorId::new("jamjam", "0.2")
+        .with_control_name("JAMJAM")
+        .with_control_type(control_types::ADD)
+        .with_fido_tag(true);
+    assert_eq!(
+        door_id.write(),
+        b"DOOR = jamjam\nVERSION = 0.2\nCONTROLNAME = JAMJAM\nCONTROLTYPE = ADD\nFIDOTAG = YES\n"
+    );
+}
+
+#[test]
+fn test_index_offset_detection() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    let mut builder = build_test_packet();
+    let msg = Message {
+        subject: BString::from("Second"),
+        text: BString::from("second message\n"),
+        ..Default::default()
+    };
+    builder.add_message(QWKMessage::from_message(&msg, 0));
+    builder.write(tmpdir.path()).unwrap();
+
+    let msg_base = QwkMessageBase::open(tmpdir.path(), true).unwrap();
+    assert!(msg_base.get_index_fixups().unwrap().is_empty());
+    assert!(QwkMessageBase::open("data/qwk", true)
+        .unwrap()
+        .get_index_fixups()
+        .unwrap()
+        .is_empty());
+
+    // rewrite the index with 0 based record numbers
+    let index_path = tmpdir.path().join("000.ndx");
+    let index = QwkMessageBase::read_qwk_index(&index_path).unwrap();
+    assert_eq!(index, vec![2, 4]);
+    let mut data = Vec::new();
+    for record in index {
+        data.extend(crate::util::basic_real::u32_to_basicreal(record - 1).to_le_bytes());
+        data.push(0);
+    }
+    std::fs::write(&index_path, data).unwrap();
+
+    let msg_base = QwkMessageBase::open(tmpdir.path(), true).unwrap();
+    assert_eq!(msg_base.get_index_fixups().unwrap(), vec![0]);
+    let mail = msg_base.read_conference(0).unwrap();
+    assert!(mail.index_fixup);
+    assert_eq!(mail.messages.len(), 2);
+    assert_eq!(mail.messages[0].subj, "Packed");
+    assert_eq!(mail.messages[1].subj, "Second");
+}
+
+#[test]
+fn test_invalid_index_records() {
+    let tmpdir = TempDir::with_prefix_in("jamtest", ".").unwrap();
+    build_test_packet().write(tmpdir.path()).unwrap();
+
+    // -1 decodes to u32::MAX, 0 and 1 would point to the packet header block
+    let mut data = Vec::new();
+    for record in [0x8180_0000, 0, 0x8100_0000, 0x8200_0000, 0x8A00_0000] {
+        data.extend(u32::to_le_bytes(record));
+        data.push(0);
+    }
+    std::fs::write(tmpdir.path().join("000.ndx"), data).unwrap();
+
+    let msg_base = QwkMessageBase::open(tmpdir.path(), true).unwrap();
+    let mail = msg_base.read_conference(0).unwrap();
+    assert!(!mail.index_fixup);
+    assert_eq!(mail.messages.len(), 1);
+    assert_eq!(mail.messages[0].subj, "Packed");
+}
+
+#[test]
+fn test_valid_header() {
+    let mut data = Vec::new();
+    QWKMessage::from_message(&Message::default(), 1)
+        .write(&mut data, false)
+        .unwrap();
+    assert!(QWKMessage::is_valid_header(&data[..128]));
+    assert!(!QWKMessage::is_valid_header(&[b' '; 128]));
+    data[122] = 0;
+    assert!(!QWKMessage::is_valid_header(&data[..128]));
+}
+
+#[test]
+fn test_kludge_without_line_break() {
+    let msg = Message {
+        text: BString::from("Subject: a kludge at the end of the text"),
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    QWKMessage::from_message(&msg, 0)
+        .write(&mut data, false)
+        .unwrap();
+
+    let mail = QWKMessage::read(&mut data.as_slice(), true).unwrap();
+    assert_eq!(mail.subj.trim_end(), b"a kludge at the end of the text");
+    assert!(mail.text.is_empty());
+}
+
+#[test]
+fn test_archive_without_messages() {
+    let files = [("CONTROL.DAT".to_string(), TEST_CONTROL_DAT.to_vec())];
+    let data = super::storage::write_archive(std::io::Cursor::new(Vec::new()), &files)
+        .unwrap()
+        .into_inner();
+
+    let msg_base = QwkMessageBase::open_archive(std::io::Cursor::new(data), true).unwrap();
+    let mut iter = msg_base.iter();
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+    assert_eq!(msg_base.active_messages(), 0);
+}
@@ -54,9 +54,24 @@ pub fn u32_to_basicreal(n: u32) -> u32 {
     result | ((exponent as u32) << 24)
 }
 
+/// Checks if `n` is a BASIC real holding a whole number in the u32 range.
+pub fn is_unsigned_basicreal(n: u32) -> bool {
+    if n == 0 {
+        return true;
+    }
+    let exponent = n >> 24;
+    if !(0x81..=0xA0).contains(&exponent) || n & 0x80_0000 != 0 {
+        return false;
+    }
+    // no fractional bits
+    let fraction_bits = 152u32.saturating_sub(exponent);
+    let value = (n & 0x7F_FFFF) | 0x80_0000;
+    value & ((1 << fraction_bits) - 1) == 0
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::util::basic_real::{basicreal_to_u32, u32_to_basicreal};
+    use crate::util::basic_real::{basicreal_to_u32, is_unsigned_basicreal, u32_to_basicreal};
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -65,4 +80,26 @@ mod tests {
             assert_eq!(i as u32, basicreal_to_u32(u32_to_basicreal(i as u32)));
         }
     }
+
+    #[test]
+    fn test_is_unsigned_basicreal() {
+        for i in [
+            0,
+            1,
+            2,
+            3,
+            127,
+            128,
+            16384,
+            0x7F_FFFF,
+            0x80_0000,
+            i32::MAX as u32,
+        ] {
+            assert!(is_unsigned_basicreal(u32_to_basicreal(i)), "{i}");
+        }
+        assert!(!is_unsigned_basicreal(u32_to_basicreal(-1i32 as u32)));
+        // 1.5
+        assert!(!is_unsigned_basicreal(0x8140_0000));
+        assert!(!is_unsigned_basicreal(u32::from_le_bytes(*b"ABCD")));
+    }
 }
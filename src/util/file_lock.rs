@@ -0,0 +1,146 @@
+//! OS level byte range locks.
+//!
+//! Message base formats define their locks on file regions (JAM locks the first byte of the .JHR file),
+//! so whole file locks aren't seen by other tools.
+//!
+//! On Linux open file description locks are used, they belong to the file handle and
+//! aren't released when another handle of the same file gets closed.
+//! Other unix systems fall back to POSIX record locks, these are per process.
+//! On Windows the lock is mandatory, locked bytes can only be accessed through the locking handle.
+use std::{fs::File, io};
+
+/// Locks `len` bytes at `offset` exclusively.
+///
+/// If `wait` is set it blocks until the lock is acquired, otherwise `false` is returned
+/// if the region is locked by someone else. The file needs to be opened for writing.
+pub(crate) fn lock_region(file: &File, offset: u64, len: u64, wait: bool) -> io::Result<bool> {
+    sys::lock(file, offset, len, wait)
+}
+
+/// Releases a lock taken by `lock_region`.
+pub(crate) fn unlock_region(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    sys::unlock(file, offset, len)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::{fs::File, io, os::unix::io::AsRawFd};
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const SET_LOCK: libc::c_int = libc::F_OFD_SETLK;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const SET_LOCK_WAIT: libc::c_int = libc::F_OFD_SETLKW;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const SET_LOCK: libc::c_int = libc::F_SETLK;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const SET_LOCK_WAIT: libc::c_int = libc::F_SETLKW;
+
+    fn set_lock(
+        file: &File,
+        lock_type: libc::c_int,
+        offset: u64,
+        len: u64,
+        wait: bool,
+    ) -> io::Result<bool> {
+        // SAFETY: flock is a plain C struct, all zero is a valid value (and l_pid needs to be 0 for OFD locks)
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = lock_type as _;
+        lock.l_whence = libc::SEEK_SET as _;
+        lock.l_start = offset as _;
+        lock.l_len = len as _;
+        let cmd = if wait { SET_LOCK_WAIT } else { SET_LOCK };
+        loop {
+            // SAFETY: the descriptor is valid for the lifetime of `file` and `lock` is initialized
+            if unsafe { libc::fcntl(file.as_raw_fd(), cmd, &lock) } != -1 {
+                return Ok(true);
+            }
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::EAGAIN) | Some(libc::EACCES) if !wait => return Ok(false),
+                _ => return Err(err),
+            }
+        }
+    }
+
+    pub fn lock(file: &File, offset: u64, len: u64, wait: bool) -> io::Result<bool> {
+        set_lock(file, libc::F_WRLCK as _, offset, len, wait)
+    }
+
+    pub fn unlock(file: &File, offset: u64, len: u64) -> io::Result<()> {
+        set_lock(file, libc::F_UNLCK as _, offset, len, false)?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{fs::File, io, os::windows::io::AsRawHandle};
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_LOCK_VIOLATION, HANDLE},
+        Storage::FileSystem::{
+            LockFileEx, UnlockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+        },
+        System::IO::{OVERLAPPED, OVERLAPPED_0, OVERLAPPED_0_0},
+    };
+
+    fn overlapped(offset: u64) -> OVERLAPPED {
+        OVERLAPPED {
+            Internal: 0,
+            InternalHigh: 0,
+            Anonymous: OVERLAPPED_0 {
+                Anonymous: OVERLAPPED_0_0 {
+                    Offset: offset as u32,
+                    OffsetHigh: (offset >> 32) as u32,
+                },
+            },
+            hEvent: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn lock(file: &File, offset: u64, len: u64, wait: bool) -> io::Result<bool> {
+        let mut flags = LOCKFILE_EXCLUSIVE_LOCK;
+        if !wait {
+            flags |= LOCKFILE_FAIL_IMMEDIATELY;
+        }
+        let mut overlapped = overlapped(offset);
+        // SAFETY: the handle is valid for the lifetime of `file`, the file isn't opened for overlapped I/O
+        let res = unsafe {
+            LockFileEx(
+                file.as_raw_handle() as HANDLE,
+                flags,
+                0,
+                len as u32,
+                (len >> 32) as u32,
+                &mut overlapped,
+            )
+        };
+        if res != 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        if !wait && err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+            return Ok(false);
+        }
+        Err(err)
+    }
+
+    pub fn unlock(file: &File, offset: u64, len: u64) -> io::Result<()> {
+        let mut overlapped = overlapped(offset);
+        // SAFETY: the handle is valid for the lifetime of `file`
+        let res = unsafe {
+            UnlockFileEx(
+                file.as_raw_handle() as HANDLE,
+                0,
+                len as u32,
+                (len >> 32) as u32,
+                &mut overlapped,
+            )
+        };
+        if res == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
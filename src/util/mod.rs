@@ -1,3 +1,4 @@
 pub mod basic_real;
 pub(crate) mod crc32;
 pub mod echmoail;
+pub(crate) mod file_lock;